@@ -0,0 +1,387 @@
+// Headless command line interface over the same tagging core used by the app, meant for machines without a display
+// every subcommand prints JSON to stdout, exiting with 0 on success, 1 when some files failed and 2 on fatal errors
+// fetched tags are kept in the library index between runs, so `fetch` then `diff` then `save` works as in the app
+
+use clap::{Args, Parser, Subcommand};
+use dzrs_core::config::{DzrsConfiguration, DzrsConfigurationParsed};
+use dzrs_core::platform_app_dir;
+use dzrs_core::types::album::fetch_albums_tags;
+use dzrs_core::types::diff::diff_track;
+use dzrs_core::types::files::{self, DzrsTrackObject, DzrsTrackObjectWrapper};
+use dzrs_core::types::history::{new_batch_id, DzrsHistory};
+use dzrs_core::types::index::DzrsLibraryIndex;
+use dzrs_core::types::tags::DeezerTagger;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(name = "dzrs-cli", version, about = "Dzrs tagging from the command line")]
+struct Cli {
+    #[command(subcommand)]
+    command: CliCommand,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    /// List the files of a directory along with their tags
+    Scan(Target),
+    /// Fetch tags from deezer, kept as pending changes until saved
+    Fetch {
+        #[command(flatten)]
+        target: Target,
+        /// Tag the files of each folder and album from a single deezer album
+        #[arg(long)]
+        album: bool,
+    },
+    /// Show the field level changes saving would write, without writing anything
+    Diff(Target),
+    /// Save the pending changes into the files
+    Save(Target),
+    /// Rename files from their tags using the file template
+    Rename(Target),
+    /// List the saves of a file, most recent first
+    History { path: String },
+    /// Revert a save, or every save of a batch with --batch
+    Revert {
+        id: String,
+        /// Treat the id as a batch, e.g. the batch printed by save
+        #[arg(long)]
+        batch: bool,
+    },
+    /// Drop every cached deezer response
+    ClearCache,
+    /// Show or change the configuration shared with the app
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Args)]
+struct Target {
+    /// Directory to load, defaults to the configured directory
+    dir: Option<String>,
+    /// Only process the given files, either absolute or relative to the directory
+    #[arg(short, long = "path")]
+    paths: Vec<String>,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print the configuration or a single key
+    Get { key: Option<String> },
+    /// Set a configuration key, e.g. `config set directory_view_path /music`
+    Set { key: String, value: String },
+}
+
+// Files loaded from a directory along with the library index they are restored from and stored into
+struct Library {
+    tracks: DzrsTrackObjectWrapper,
+    index: DzrsLibraryIndex,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let app_dir = platform_app_dir();
+    if !app_dir.exists() {
+        let _ = std::fs::create_dir_all(&app_dir);
+    }
+    let mut config = DzrsConfiguration::load(app_dir.join("config.json"));
+    let conf = config.parsed();
+
+    let result = match cli.command {
+        CliCommand::Scan(target) => scan(&target, &conf),
+        CliCommand::Fetch { target, album } => fetch(&target, album, &conf).await,
+        CliCommand::Diff(target) => diff(&target, &conf),
+        CliCommand::Save(target) => save(&target, &conf),
+        CliCommand::Rename(target) => rename(&target, &conf),
+        CliCommand::History { path } => history(&path),
+        CliCommand::Revert { id, batch } => revert(&id, batch, &conf),
+        CliCommand::ClearCache => clear_cache(&conf),
+        CliCommand::Config { action } => config_action(action, &mut config),
+    };
+    match result {
+        Ok((output, errors)) => {
+            let failed = !errors.is_empty();
+            let mut output = output;
+            output["errors"] = json!(errors);
+            println!("{}", serde_json::to_string_pretty(&output).unwrap_or_default());
+            match failed {
+                true => ExitCode::from(1),
+                false => ExitCode::SUCCESS,
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", json!({ "error": err }));
+            ExitCode::from(2)
+        }
+    }
+}
+
+// Every subcommand returns its JSON output along with the errors of single files, fatal errors abort instead
+type CliResult = Result<(Value, Vec<String>), String>;
+
+fn scan(target: &Target, conf: &DzrsConfigurationParsed) -> CliResult {
+    let lib = Library::load(target, conf)?;
+    let paths = lib.selected(target)?;
+    let tracks: Vec<_> = lib.tracks.iter().filter(|tr| paths.contains(&tr.file_path)).collect();
+    let output = json!({ "tracks": tracks });
+    lib.persist()?;
+    Ok((output, Vec::new()))
+}
+
+async fn fetch(target: &Target, album: bool, conf: &DzrsConfigurationParsed) -> CliResult {
+    let mut lib = Library::load(target, conf)?;
+    let tagger = DeezerTagger::with_cache(platform_app_dir().join("cache"), conf);
+    let mut results = Vec::new();
+    let mut errors = Vec::new();
+    if album {
+        let paths = lib.selected(target)?;
+        let mut trs: Vec<DzrsTrackObject> = paths
+            .iter()
+            .filter_map(|p| lib.tracks.get_track_obj(p).cloned())
+            .collect();
+        errors = fetch_albums_tags(&mut trs, &tagger, conf).await;
+        for tr in trs {
+            results.push(json!({ "path": tr.file_path, "tagsStatus": tr.tags_status, "tagsToSave": tr.tags_to_save }));
+            lib.tracks.replace_track_obj(tr)?;
+        }
+        lib.persist()?;
+        return Ok((json!({ "tracks": results }), errors));
+    };
+    for p in lib.selected(target)? {
+        let tr = match lib.tracks.get_track_obj_mut(&p) {
+            Some(tr) => tr,
+            None => continue,
+        };
+        let fetched = tr.fetch_tags(&tagger, conf).await;
+        if let Err(err) = &fetched {
+            errors.push(format!("{}: {}", p, err));
+        };
+        // Sources are sorted best first, the applied one if any is the first
+        let score = tr.tags_sources.first().map(|s| s.score);
+        results.push(json!({ "path": p, "tagsStatus": tr.tags_status, "score": score, "tagsToSave": tr.tags_to_save }));
+    }
+    lib.persist()?;
+    Ok((json!({ "tracks": results }), errors))
+}
+
+fn diff(target: &Target, conf: &DzrsConfigurationParsed) -> CliResult {
+    let lib = Library::load(target, conf)?;
+    let mut results = Vec::new();
+    let mut errors = Vec::new();
+    for p in lib.selected(target)? {
+        let tr = match lib.tracks.get_track_obj(&p) {
+            Some(tr) if tr.tags != tr.tags_to_save => tr,
+            _ => continue,
+        };
+        match diff_track(tr, &tr.tags_to_save, conf) {
+            Ok(diff) if !diff.changes.is_empty() => results.push(diff),
+            Ok(_) => (),
+            Err(err) => errors.push(format!("{}: {}", p, err)),
+        };
+    }
+    lib.persist()?;
+    Ok((json!({ "tracks": results }), errors))
+}
+
+fn save(target: &Target, conf: &DzrsConfigurationParsed) -> CliResult {
+    let mut lib = Library::load(target, conf)?;
+    let mut history = DzrsHistory::load(platform_app_dir().join("history"));
+    let batch = new_batch_id();
+    let mut results = Vec::new();
+    let mut errors = Vec::new();
+    for p in lib.selected(target)? {
+        let tags = match lib.tracks.get_track_obj(&p) {
+            Some(tr) if tr.tags != tr.tags_to_save => tr.tags_to_save.clone(),
+            _ => continue,
+        };
+        // Files which cannot be backed up are not saved
+        let entry = match conf.history_enabled {
+            true => match history.record(&p, &batch, "save") {
+                Ok(id) => Some(id),
+                Err(err) => {
+                    errors.push(format!("{}: {}", p, err));
+                    continue;
+                }
+            },
+            false => None,
+        };
+        match files::save_track(&p, &tags, conf) {
+            Ok((saved, errs)) => {
+                errors.extend(errs.into_iter().map(|err| format!("{}: {}", p, err)));
+                if let Some(id) = entry.filter(|_| saved.file_path != p) {
+                    history.set_path(&id, &saved.file_path)?;
+                };
+                results.push(json!({ "path": p, "newPath": saved.file_path }));
+                lib.tracks.apply_saved(&p, saved)?;
+            }
+            Err(err) => {
+                if let Some(id) = entry {
+                    history.discard(&id)?;
+                };
+                errors.push(format!("{}: {}", p, err));
+            }
+        };
+    }
+    if conf.history_enabled {
+        history.prune(conf)?;
+    };
+    lib.persist()?;
+    Ok((json!({ "batch": batch, "saved": results }), errors))
+}
+
+fn rename(target: &Target, conf: &DzrsConfigurationParsed) -> CliResult {
+    let mut lib = Library::load(target, conf)?;
+    let mut results = Vec::new();
+    let mut errors = Vec::new();
+    for p in lib.selected(target)? {
+        let i = match lib.tracks.iter().position(|tr| tr.file_path == p) {
+            Some(i) => i,
+            None => continue,
+        };
+        match lib.tracks.rename_from_template(&p, conf) {
+            Ok(()) if lib.tracks[i].file_path != p => {
+                results.push(json!({ "path": p, "newPath": lib.tracks[i].file_path }));
+            }
+            Ok(()) => (),
+            Err(err) => errors.push(format!("{}: {}", p, err)),
+        };
+    }
+    lib.persist()?;
+    Ok((json!({ "renamed": results }), errors))
+}
+
+fn history(path: &str) -> CliResult {
+    let history = DzrsHistory::load(platform_app_dir().join("history"));
+    let entries = history.entries_for(&absolute(path));
+    Ok((json!({ "entries": entries }), Vec::new()))
+}
+
+fn clear_cache(conf: &DzrsConfigurationParsed) -> CliResult {
+    DeezerTagger::with_cache(platform_app_dir().join("cache"), conf).clear_cache()?;
+    Ok((json!({ "cleared": true }), Vec::new()))
+}
+
+fn revert(id: &str, batch: bool, conf: &DzrsConfigurationParsed) -> CliResult {
+    let mut history = DzrsHistory::load(platform_app_dir().join("history"));
+    let revert_batch = new_batch_id();
+    match batch {
+        true => match history.revert_batch(id, &revert_batch, conf) {
+            Ok(paths) => Ok((json!({ "batch": revert_batch, "reverted": paths }), Vec::new())),
+            Err(errors) => Ok((json!({ "batch": revert_batch, "reverted": [] }), errors)),
+        },
+        false => {
+            let path = history.revert(id, &revert_batch, conf)?;
+            Ok((json!({ "batch": revert_batch, "reverted": [path] }), Vec::new()))
+        }
+    }
+}
+
+// Keys are accepted both in snake_case, as used by config_set in the app, and in the camelCase of config.json
+fn config_action(action: ConfigAction, config: &mut DzrsConfiguration) -> CliResult {
+    let current = serde_json::to_value(&*config).map_err(|err| err.to_string())?;
+    match action {
+        ConfigAction::Get { key: None } => Ok((json!({ "config": current }), Vec::new())),
+        ConfigAction::Get { key: Some(key) } => match current.get(camel_case(&key)) {
+            Some(value) => Ok((json!({ "key": key, "value": value }), Vec::new())),
+            None => Err(format!("Unknown configuration key {}", key)),
+        },
+        ConfigAction::Set { key, value } => {
+            if current.get(camel_case(&key)).is_none() {
+                return Err(format!("Unknown configuration key {}", key));
+            };
+            config.update(snake_case(&key), value.clone());
+            config.save().map_err(|err| err.to_string())?;
+            Ok((json!({ "key": key, "value": value }), Vec::new()))
+        }
+    }
+}
+
+fn absolute(path: &str) -> String {
+    match (Path::new(path).is_absolute(), std::env::current_dir()) {
+        // Collecting the components drops the "." ones
+        (false, Ok(cwd)) => cwd
+            .join(path)
+            .components()
+            .collect::<PathBuf>()
+            .to_str()
+            .unwrap_or(path)
+            .to_string(),
+        _ => path.to_string(),
+    }
+}
+
+fn camel_case(key: &str) -> String {
+    let mut result = String::new();
+    let mut upper = false;
+    for c in key.chars() {
+        match (c, upper) {
+            ('_', _) => upper = true,
+            (c, true) => {
+                result.extend(c.to_uppercase());
+                upper = false;
+            }
+            (c, false) => result.push(c),
+        };
+    }
+    result
+}
+
+fn snake_case(key: &str) -> String {
+    let mut result = String::new();
+    for c in key.chars() {
+        if c.is_uppercase() {
+            result.push('_');
+            result.extend(c.to_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+impl Library {
+    // Scans the directory, unchanged files are restored from the library index and the others are read
+    fn load(target: &Target, conf: &DzrsConfigurationParsed) -> Result<Self, String> {
+        // Paths are kept absolute, as in the app, so the library index and history are shared with it
+        let dir = match &target.dir {
+            Some(dir) => absolute(dir),
+            None => conf.directory_view_path.clone(),
+        };
+        let mut tracks = DzrsTrackObjectWrapper::new(dir, conf)?;
+        let index = DzrsLibraryIndex::load(platform_app_dir().join("library.json"));
+        index.restore(&mut tracks);
+        tracks.load_tags(conf);
+        Ok(Self { tracks, index })
+    }
+
+    // Paths of the supported files matching the target, all of them when no paths are given
+    fn selected(&self, target: &Target) -> Result<Vec<String>, String> {
+        let supported = self.tracks.iter().filter(|tr| !tr.file_type.is_empty());
+        if target.paths.is_empty() {
+            return Ok(supported.map(|tr| tr.file_path.clone()).collect());
+        };
+        let mut paths = Vec::new();
+        for p in target.paths.iter() {
+            let canonical = Path::new(p).canonicalize().ok();
+            let found = supported.clone().find(|tr| {
+                &tr.file_path == p
+                    || &tr.relative_path == p
+                    || (canonical.is_some() && Path::new(&tr.file_path).canonicalize().ok() == canonical)
+            });
+            match found {
+                Some(tr) => paths.push(tr.file_path.clone()),
+                None => return Err(format!("{} is not a supported file within the directory", p)),
+            };
+        }
+        Ok(paths)
+    }
+
+    fn persist(mut self) -> Result<(), String> {
+        self.index.update(&self.tracks.root, &self.tracks.items);
+        self.index.save()
+    }
+}
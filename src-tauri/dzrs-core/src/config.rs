@@ -1,12 +1,12 @@
+use dirs_next::audio_dir;
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 use std::fs::File;
 use std::io::{BufReader, Write};
 use std::path::Path;
-use tauri::api::path::audio_dir;
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", default)]
 pub struct DzrsConfiguration {
     #[serde(rename = "_path")]
     _path: String,
@@ -18,6 +18,14 @@ pub struct DzrsConfiguration {
     pub directory_view_path: String,
     pub directory_output: String,
     pub directory_move_on_save: String,
+    pub directory_template: String,
+    pub directory_recursive: String,
+    pub directory_max_depth: String,
+    pub directory_follow_symlinks: String,
+    pub directory_ignore: String,
+    pub directory_delete_permanently: String,
+    pub history_enabled: String,
+    pub history_max_entries: String,
     pub filter_download_genre: String,
     pub filter_download_date: String,
     pub filter_download_composer: String,
@@ -31,6 +39,14 @@ pub struct DzrsConfiguration {
     pub tag_pad_disk: String,
     pub tag_pad_disk_total: String,
     pub tag_separator: String,
+    pub tag_cover_size: String,
+    pub tag_cover_policy: String,
+    pub tag_cover_resize: String,
+    pub tag_cover_max_size: String,
+    pub tag_cover_quality: String,
+    pub tag_keep_mtime: String,
+    pub tag_match_threshold: String,
+    pub tag_match_review: String,
     pub tag_dz_title: String,
     pub tag_dz_artist: String,
     pub tag_dz_album: String,
@@ -58,11 +74,20 @@ pub struct DzrsConfiguration {
     pub tag_dz_explicit: String,
     pub tag_dz_replaygain_track_gain: String,
     pub tag_dz_source_id: String,
+    pub tag_dz_cover: String,
     pub tag_prefer_sync_lyrics: String,
     pub tag_fetch_with_filename: String,
+    pub tag_filename_pattern: String,
+    pub tag_filename_before_fetch: String,
+    pub tag_fetch_exact: String,
+    pub cache_enabled: String,
+    pub cache_ttl_hours: String,
+    pub request_rate_limit: String,
+    pub request_max_retries: String,
     pub tag_date_as_year: String,
     pub tag_originaldate_as_year: String,
     pub tag_clear_extra_tags: String,
+    pub tag_multiple_values: String,
     pub tag_remove_feat_title: String,
 }
 
@@ -75,6 +100,14 @@ pub struct DzrsConfigurationParsed {
     pub directory_view_path: String,
     pub directory_output: String,
     pub directory_move_on_save: bool,
+    pub directory_template: String,
+    pub directory_recursive: bool,
+    pub directory_max_depth: u32,
+    pub directory_follow_symlinks: bool,
+    pub directory_ignore: String,
+    pub directory_delete_permanently: bool,
+    pub history_enabled: bool,
+    pub history_max_entries: u32,
     pub filter_download_genre: bool,
     pub filter_download_date: bool,
     pub filter_download_composer: bool,
@@ -88,6 +121,14 @@ pub struct DzrsConfigurationParsed {
     pub tag_pad_disk: bool,
     pub tag_pad_disk_total: bool,
     pub tag_separator: String,
+    pub tag_cover_size: u32,
+    pub tag_cover_policy: String,
+    pub tag_cover_resize: bool,
+    pub tag_cover_max_size: u32,
+    pub tag_cover_quality: u8,
+    pub tag_keep_mtime: bool,
+    pub tag_match_threshold: u8,
+    pub tag_match_review: u8,
     pub tag_dz_title: bool,
     pub tag_dz_artist: bool,
     pub tag_dz_album: bool,
@@ -115,11 +156,20 @@ pub struct DzrsConfigurationParsed {
     pub tag_dz_explicit: bool,
     pub tag_dz_replaygain_track_gain: bool,
     pub tag_dz_source_id: bool,
+    pub tag_dz_cover: bool,
     pub tag_prefer_sync_lyrics: bool,
     pub tag_fetch_with_filename: bool,
+    pub tag_filename_pattern: String,
+    pub tag_filename_before_fetch: bool,
+    pub tag_fetch_exact: bool,
+    pub cache_enabled: bool,
+    pub cache_ttl_hours: u32,
+    pub request_rate_limit: u32,
+    pub request_max_retries: u32,
     pub tag_date_as_year: bool,
     pub tag_originaldate_as_year: bool,
     pub tag_clear_extra_tags: bool,
+    pub tag_multiple_values: bool,
     pub tag_remove_feat_title: bool,
 }
 
@@ -160,6 +210,14 @@ impl DzrsConfiguration {
             "directory_view_path" => self.directory_view_path = value,
             "directory_output" => self.directory_output = value,
             "directory_move_on_save" => self.directory_move_on_save = value,
+            "directory_template" => self.directory_template = value,
+            "directory_recursive" => self.directory_recursive = value,
+            "directory_max_depth" => self.directory_max_depth = value,
+            "directory_follow_symlinks" => self.directory_follow_symlinks = value,
+            "directory_ignore" => self.directory_ignore = value,
+            "directory_delete_permanently" => self.directory_delete_permanently = value,
+            "history_enabled" => self.history_enabled = value,
+            "history_max_entries" => self.history_max_entries = value,
             "filter_download_genre" => self.filter_download_genre = value,
             "filter_download_date" => self.filter_download_date = value,
             "filter_download_composer" => self.filter_download_composer = value,
@@ -173,6 +231,14 @@ impl DzrsConfiguration {
             "tag_pad_disk" => self.tag_pad_disk = value,
             "tag_pad_disk_total" => self.tag_pad_disk_total = value,
             "tag_separator" => self.tag_separator = value,
+            "tag_cover_size" => self.tag_cover_size = value,
+            "tag_cover_policy" => self.tag_cover_policy = value,
+            "tag_cover_resize" => self.tag_cover_resize = value,
+            "tag_cover_max_size" => self.tag_cover_max_size = value,
+            "tag_cover_quality" => self.tag_cover_quality = value,
+            "tag_keep_mtime" => self.tag_keep_mtime = value,
+            "tag_match_threshold" => self.tag_match_threshold = value,
+            "tag_match_review" => self.tag_match_review = value,
             "tag_dz_title" => self.tag_dz_title = value,
             "tag_dz_artist" => self.tag_dz_artist = value,
             "tag_dz_album" => self.tag_dz_album = value,
@@ -200,11 +266,20 @@ impl DzrsConfiguration {
             "tag_dz_explicit" => self.tag_dz_explicit = value,
             "tag_dz_replaygain_track_gain" => self.tag_dz_replaygain_track_gain = value,
             "tag_dz_source_id" => self.tag_dz_source_id = value,
+            "tag_dz_cover" => self.tag_dz_cover = value,
             "tag_prefer_sync_lyrics" => self.tag_prefer_sync_lyrics = value,
             "tag_fetch_with_filename" => self.tag_fetch_with_filename = value,
+            "tag_filename_pattern" => self.tag_filename_pattern = value,
+            "tag_filename_before_fetch" => self.tag_filename_before_fetch = value,
+            "tag_fetch_exact" => self.tag_fetch_exact = value,
+            "cache_enabled" => self.cache_enabled = value,
+            "cache_ttl_hours" => self.cache_ttl_hours = value,
+            "request_rate_limit" => self.request_rate_limit = value,
+            "request_max_retries" => self.request_max_retries = value,
             "tag_date_as_year" => self.tag_date_as_year = value,
             "tag_originaldate_as_year" => self.tag_originaldate_as_year = value,
             "tag_clear_extra_tags" => self.tag_clear_extra_tags = value,
+            "tag_multiple_values" => self.tag_multiple_values = value,
             "tag_remove_feat_title" => self.tag_remove_feat_title = value,
             _ => (),
         }
@@ -226,6 +301,14 @@ impl DzrsConfiguration {
             directory_view_path: self.directory_view_path.clone(),
             directory_output: self.directory_output.clone(),
             directory_move_on_save: self.directory_move_on_save.parse().unwrap_or(false),
+            directory_template: self.directory_template.clone(),
+            directory_recursive: self.directory_recursive.parse().unwrap_or(false),
+            directory_max_depth: self.directory_max_depth.parse().unwrap_or(5),
+            directory_follow_symlinks: self.directory_follow_symlinks.parse().unwrap_or(false),
+            directory_ignore: self.directory_ignore.clone(),
+            directory_delete_permanently: self.directory_delete_permanently.parse().unwrap_or(false),
+            history_enabled: self.history_enabled.parse().unwrap_or(true),
+            history_max_entries: self.history_max_entries.parse().unwrap_or(500),
             filter_download_genre: self.filter_download_genre.parse().unwrap_or(false),
             filter_download_date: self.filter_download_date.parse().unwrap_or(false),
             filter_download_composer: self.filter_download_composer.parse().unwrap_or(false),
@@ -239,6 +322,14 @@ impl DzrsConfiguration {
             tag_pad_disk: self.tag_pad_disk.parse().unwrap_or(false),
             tag_pad_disk_total: self.tag_pad_disk_total.parse().unwrap_or(false),
             tag_separator: self.tag_separator.clone(),
+            tag_cover_size: self.tag_cover_size.parse().unwrap_or(1200),
+            tag_cover_policy: self.tag_cover_policy.clone(),
+            tag_cover_resize: self.tag_cover_resize.parse().unwrap_or(false),
+            tag_cover_max_size: self.tag_cover_max_size.parse().unwrap_or(1200),
+            tag_cover_quality: self.tag_cover_quality.parse().unwrap_or(90),
+            tag_keep_mtime: self.tag_keep_mtime.parse().unwrap_or(false),
+            tag_match_threshold: self.tag_match_threshold.parse().unwrap_or(50),
+            tag_match_review: self.tag_match_review.parse().unwrap_or(80),
             tag_dz_title: self.tag_dz_title.parse().unwrap_or(true),
             tag_dz_artist: self.tag_dz_artist.parse().unwrap_or(true),
             tag_dz_album: self.tag_dz_album.parse().unwrap_or(true),
@@ -266,11 +357,20 @@ impl DzrsConfiguration {
             tag_dz_explicit: self.tag_dz_explicit.parse().unwrap_or(true),
             tag_dz_replaygain_track_gain: self.tag_dz_replaygain_track_gain.parse().unwrap_or(true),
             tag_dz_source_id: self.tag_dz_source_id.parse().unwrap_or(true),
+            tag_dz_cover: self.tag_dz_cover.parse().unwrap_or(true),
             tag_prefer_sync_lyrics: self.tag_prefer_sync_lyrics.parse().unwrap_or(true),
             tag_fetch_with_filename: self.tag_fetch_with_filename.parse().unwrap_or(true),
+            tag_filename_pattern: self.tag_filename_pattern.clone(),
+            tag_filename_before_fetch: self.tag_filename_before_fetch.parse().unwrap_or(false),
+            tag_fetch_exact: self.tag_fetch_exact.parse().unwrap_or(true),
+            cache_enabled: self.cache_enabled.parse().unwrap_or(true),
+            cache_ttl_hours: self.cache_ttl_hours.parse().unwrap_or(168),
+            request_rate_limit: self.request_rate_limit.parse().unwrap_or(10),
+            request_max_retries: self.request_max_retries.parse().unwrap_or(3),
             tag_date_as_year: self.tag_date_as_year.parse().unwrap_or(true),
             tag_originaldate_as_year: self.tag_originaldate_as_year.parse().unwrap_or(true),
             tag_clear_extra_tags: self.tag_clear_extra_tags.parse().unwrap_or(false),
+            tag_multiple_values: self.tag_multiple_values.parse().unwrap_or(true),
             tag_remove_feat_title: self.tag_remove_feat_title.parse().unwrap_or(true),
         }
     }
@@ -291,6 +391,14 @@ impl Default for DzrsConfiguration {
             directory_view_path: audio_path,
             directory_output: "".into(),
             directory_move_on_save: "false".into(),
+            directory_template: "".into(),
+            directory_recursive: "false".into(),
+            directory_max_depth: "5".into(),
+            directory_follow_symlinks: "false".into(),
+            directory_ignore: ".stfolder;@eaDir;.*".into(),
+            directory_delete_permanently: "false".into(),
+            history_enabled: "true".into(),
+            history_max_entries: "500".into(),
             filter_download_genre: "false".into(),
             filter_download_date: "false".into(),
             filter_download_composer: "false".into(),
@@ -304,6 +412,14 @@ impl Default for DzrsConfiguration {
             tag_pad_disk: "false".into(),
             tag_pad_disk_total: "false".into(),
             tag_separator: "; ".into(),
+            tag_cover_size: "1200".into(),
+            tag_cover_policy: "replace".into(),
+            tag_cover_resize: "false".into(),
+            tag_cover_max_size: "1200".into(),
+            tag_cover_quality: "90".into(),
+            tag_keep_mtime: "false".into(),
+            tag_match_threshold: "50".into(),
+            tag_match_review: "80".into(),
             tag_dz_title: "true".into(),
             tag_dz_artist: "true".into(),
             tag_dz_album: "true".into(),
@@ -331,11 +447,20 @@ impl Default for DzrsConfiguration {
             tag_dz_explicit: "true".into(),
             tag_dz_replaygain_track_gain: "true".into(),
             tag_dz_source_id: "true".into(),
+            tag_dz_cover: "true".into(),
             tag_prefer_sync_lyrics: "true".into(),
             tag_fetch_with_filename: "true".into(),
+            tag_filename_pattern: "%artist% - %title%".into(),
+            tag_filename_before_fetch: "false".into(),
+            tag_fetch_exact: "true".into(),
+            cache_enabled: "true".into(),
+            cache_ttl_hours: "168".into(),
+            request_rate_limit: "10".into(),
+            request_max_retries: "3".into(),
             tag_date_as_year: "true".into(),
             tag_originaldate_as_year: "true".into(),
             tag_clear_extra_tags: "false".into(),
+            tag_multiple_values: "true".into(),
             tag_remove_feat_title: "true".into(),
         }
     }
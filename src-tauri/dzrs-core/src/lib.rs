@@ -0,0 +1,37 @@
+pub mod config;
+pub mod types;
+
+use std::env;
+use std::env::consts::OS;
+use std::path::PathBuf;
+
+// Directory holding the configuration and the library index, shared by the app and dzrs-cli
+pub fn platform_app_dir() -> PathBuf {
+    let mut path = PathBuf::new();
+    match OS {
+        "linux" => {
+            if let Ok(xdg_data_home) = env::var("XDG_DATA_HOME") {
+                path.push(xdg_data_home);
+            } else if let Ok(home) = env::var("HOME") {
+                path.push(home);
+                path.push(".local/share");
+            }
+            path.push("Dzrs");
+        }
+        "macos" => {
+            if let Ok(home) = env::var("HOME") {
+                path.push(home);
+                path.push("Library/Application Support");
+                path.push("Dzrs");
+            }
+        }
+        "windows" => {
+            if let Ok(appdata) = env::var("APPDATA") {
+                path.push(appdata);
+                path.push("Dzrs");
+            }
+        }
+        _ => (),
+    }
+    path
+}
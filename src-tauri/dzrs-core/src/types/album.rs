@@ -0,0 +1,348 @@
+use crate::config::DzrsConfigurationParsed;
+use crate::types::files::{DzrsTrackObject, DzrsTrackObjectTagState};
+use crate::types::matching::{match_score, text_similarity, DzrsMatchCandidate};
+use crate::types::tags::DeezerTagger;
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// Albums found on deezer, only the best ones get their tracklist fetched for aligning the files
+const ALBUM_REFINE_COUNT: usize = 3;
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DzrsAlbumSource {
+    pub id: u64,
+    pub title: String,
+    pub artist: String,
+    pub track_count: u32,
+}
+
+// Groups tracks by folder and album tag, the indices of each group are in the order of the given tracks
+// files without an album tag are grouped by folder alone
+pub fn group_by_album(tracks: &[DzrsTrackObject]) -> Vec<Vec<usize>> {
+    let mut keys: Vec<(String, String)> = Vec::new();
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (i, tr) in tracks.iter().enumerate() {
+        let folder = Path::new(&tr.file_path)
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let key = (folder, tr.tags.album.trim().to_lowercase());
+        match keys.iter().position(|k| k == &key) {
+            Some(g) => groups[g].push(i),
+            None => {
+                keys.push(key);
+                groups.push(vec![i]);
+            }
+        };
+    }
+    groups
+}
+
+// Tags the tracks album by album, see group_by_album and fetch_album_tags, the tracks are updated in place
+// Returns the errors of each file which could not be tagged
+pub async fn fetch_albums_tags(
+    tracks: &mut [DzrsTrackObject],
+    tagger: &DeezerTagger,
+    conf: &DzrsConfigurationParsed,
+) -> Vec<String> {
+    let mut errors = Vec::new();
+    for group in group_by_album(tracks) {
+        let mut group_tracks: Vec<DzrsTrackObject> = group.iter().map(|i| tracks[*i].clone()).collect();
+        errors.extend(fetch_album_tags(&mut group_tracks, tagger, conf).await);
+        for (i, tr) in group.into_iter().zip(group_tracks) {
+            tracks[i] = tr;
+        }
+    }
+    errors
+}
+
+// Tags a group of tracks from a single deezer album, the album is searched once using the tags of the group
+// and the tracklists of the best results are aligned to the files, the album aligning the most files wins
+// files aligned scoring below tag_match_review are left Successfull for review, files left out are Unsuccessfull
+// Returns the errors of each file which could not be tagged
+pub async fn fetch_album_tags(
+    tracks: &mut [DzrsTrackObject],
+    tagger: &DeezerTagger,
+    conf: &DzrsConfigurationParsed,
+) -> Vec<String> {
+    if tracks.is_empty() {
+        return Vec::new();
+    };
+    let (album, artist) = album_query_tags(tracks);
+    let query = match artist.is_empty() {
+        true => format!(r#"album:"{}""#, album),
+        false => format!(r#"album:"{}" artist:"{}""#, album, artist),
+    };
+    let mut sources = match tagger.fetch_album_sources(&query).await {
+        Ok(sources) if !sources.is_empty() => sources,
+        Ok(_) => return fail_all(tracks, &format!("No albums found on deezer for query {}", query)),
+        Err(err) => return fail_all(tracks, &err),
+    };
+    // Closest title and artist first, ties broken by the tracks count closest to the group size
+    let album_score = |s: &DzrsAlbumSource| {
+        let similarity = text_similarity(&album, &s.title) + text_similarity(&artist, &s.artist);
+        let count_diff = s.track_count.abs_diff(tracks.len() as u32) as f64;
+        similarity - count_diff / 1000.0
+    };
+    sources.sort_by(|a, b| album_score(b).total_cmp(&album_score(a)));
+
+    let mut best: Option<(DzrsAlbumSource, Vec<Option<(u64, u8)>>, u32)> = None;
+    for source in sources.into_iter().take(ALBUM_REFINE_COUNT) {
+        let mut candidates = match tagger.fetch_album_tracks(source.id).await {
+            Ok(candidates) => candidates,
+            Err(_) => continue,
+        };
+        for (_, c) in candidates.iter_mut() {
+            c.album = source.title.clone();
+        }
+        let alignment = align_tracks(tracks, &candidates, conf.tag_match_threshold);
+        let total: u32 = alignment.iter().flatten().map(|(_, score)| *score as u32).sum();
+        let better = match &best {
+            Some((_, _, best_total)) => total > *best_total,
+            None => true,
+        };
+        if better {
+            best = Some((source, alignment, total));
+        };
+    }
+    let (source, alignment) = match best {
+        Some((source, alignment, total)) if total > 0 => (source, alignment),
+        _ => {
+            return fail_all(
+                tracks,
+                &format!("No album on deezer matches the files for query {}", query),
+            )
+        }
+    };
+
+    let mut errors = Vec::new();
+    for (tr, aligned) in tracks.iter_mut().zip(alignment) {
+        match aligned {
+            Some((id, score)) => {
+                tr.fetch_source(tagger, id, conf).await;
+                if score < conf.tag_match_review {
+                    tr.tags_status = DzrsTrackObjectTagState::Successfull;
+                };
+            }
+            None => {
+                tr.tags_status = DzrsTrackObjectTagState::Unsuccessfull;
+                errors.push(format!(
+                    "{}: no track of {} matches the file",
+                    tr.file_path, source.title
+                ));
+            }
+        };
+    }
+    errors
+}
+
+// Album and artist searched for a non empty group, the folder name stands for a missing album tag
+// the artist is the one most tracks share, so a first track featuring another artist does not steer the search
+fn album_query_tags(tracks: &[DzrsTrackObject]) -> (String, String) {
+    let first = &tracks[0];
+    let album = match first.tags.album.is_empty() {
+        true => Path::new(&first.file_path)
+            .parent()
+            .and_then(|p| p.file_name())
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+        false => first.tags.album.clone(),
+    };
+    let mut artists: Vec<(String, usize)> = Vec::new();
+    for tr in tracks {
+        let artist = match tr.tags.album_artist.is_empty() {
+            true => tr.tags.artist.first().cloned().unwrap_or_default(),
+            false => tr.tags.album_artist.clone(),
+        };
+        if artist.is_empty() {
+            continue;
+        };
+        match artists.iter_mut().find(|(a, _)| *a == artist) {
+            Some((_, count)) => *count += 1,
+            None => artists.push((artist, 1)),
+        };
+    }
+    // Ties go to the artist found first
+    let artist = artists
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(artist, _)| artist)
+        .unwrap_or_default();
+    // '&' messes up the search, so it is stripped
+    (album.replace('&', ""), artist.replace('&', ""))
+}
+
+// Pairs each track with an album track, best scoring pairs first so every album track is used at most once
+// pairs scoring below the threshold are never made
+fn align_tracks(
+    tracks: &[DzrsTrackObject],
+    candidates: &[(u64, DzrsMatchCandidate)],
+    threshold: u8,
+) -> Vec<Option<(u64, u8)>> {
+    let mut pairs: Vec<(u8, usize, usize)> = Vec::new();
+    for (i, tr) in tracks.iter().enumerate() {
+        let tags = tr.match_tags(&tr.tags);
+        for (j, (_, c)) in candidates.iter().enumerate() {
+            let score = match_score(&tags, tr.audio_properties.duration, c);
+            if score >= threshold {
+                pairs.push((score, i, j));
+            };
+        }
+    }
+    pairs.sort_by_key(|p| std::cmp::Reverse(p.0));
+    let mut aligned: Vec<Option<(u64, u8)>> = vec![None; tracks.len()];
+    let mut used = vec![false; candidates.len()];
+    for (score, i, j) in pairs {
+        if aligned[i].is_none() && !used[j] {
+            aligned[i] = Some((candidates[j].0, score));
+            used[j] = true;
+        };
+    }
+    aligned
+}
+
+fn fail_all(tracks: &mut [DzrsTrackObject], err: &str) -> Vec<String> {
+    tracks
+        .iter_mut()
+        .map(|tr| {
+            tr.tags_status = DzrsTrackObjectTagState::Unsuccessfull;
+            format!("{}: {}", tr.file_path, err)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::audio::DzrsTrackObjectProperties;
+    use crate::types::tags::DzrsTrackObjectTags;
+
+    fn track(file_path: &str, tags: DzrsTrackObjectTags) -> DzrsTrackObject {
+        DzrsTrackObject {
+            file_path: file_path.to_string(),
+            file_name: Path::new(file_path).file_name().unwrap().to_string_lossy().to_string(),
+            audio_properties: DzrsTrackObjectProperties {
+                duration: 200,
+                ..Default::default()
+            },
+            tags,
+            ..Default::default()
+        }
+    }
+
+    fn numbered(title: &str, track_number: &str, disk_number: &str) -> DzrsTrackObjectTags {
+        DzrsTrackObjectTags {
+            title: title.to_string(),
+            track_number: track_number.to_string(),
+            disk_number: disk_number.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn album(album: &str, album_artist: &str, artist: &str) -> DzrsTrackObjectTags {
+        DzrsTrackObjectTags {
+            album: album.to_string(),
+            album_artist: album_artist.to_string(),
+            artist: vec![artist.to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn candidate(id: u64, title: &str, track_number: &str, disk_number: &str) -> (u64, DzrsMatchCandidate) {
+        let candidate = DzrsMatchCandidate {
+            title: title.to_string(),
+            duration: 200,
+            track_number: track_number.to_string(),
+            disk_number: disk_number.to_string(),
+            ..Default::default()
+        };
+        (id, candidate)
+    }
+
+    #[test]
+    fn group_by_album_splits_folders_and_albums() {
+        let tracks = vec![
+            track("/music/a/1.flac", album("First", "", "")),
+            track("/music/a/2.flac", album("Second", "", "")),
+            track("/music/b/3.flac", album("First", "", "")),
+            track("/music/a/4.flac", album("First", "", "")),
+        ];
+        assert_eq!(group_by_album(&tracks), vec![vec![0, 3], vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn group_by_album_ignores_case_and_spaces() {
+        let tracks = vec![
+            track("/music/a/1.flac", album("Album", "", "")),
+            track("/music/a/2.flac", album(" ALBUM ", "", "")),
+            track("/music/a/3.flac", album("", "", "")),
+            track("/music/a/4.flac", album("", "", "")),
+        ];
+        assert_eq!(group_by_album(&tracks), vec![vec![0, 1], vec![2, 3]]);
+    }
+
+    #[test]
+    fn album_query_tags_uses_the_most_common_artist() {
+        let tracks = vec![
+            track("/music/Album/1.flac", album("", "", "Artist feat. Guest")),
+            track("/music/Album/2.flac", album("", "", "Artist")),
+            track("/music/Album/3.flac", album("", "Artist", "Other")),
+        ];
+        assert_eq!(album_query_tags(&tracks), ("Album".to_string(), "Artist".to_string()));
+        let tracks = vec![
+            track("/music/a/1.flac", album("Rock & Roll", "", "First")),
+            track("/music/a/2.flac", album("Rock & Roll", "", "Second")),
+        ];
+        assert_eq!(
+            album_query_tags(&tracks),
+            ("Rock  Roll".to_string(), "First".to_string())
+        );
+    }
+
+    #[test]
+    fn align_tracks_uses_track_and_disk_numbers() {
+        let tracks = vec![
+            track("/music/a/1.flac", numbered("Intro", "1", "2")),
+            track("/music/a/2.flac", numbered("Intro", "1/10", "1")),
+        ];
+        let candidates = vec![candidate(10, "Intro", "1", "1"), candidate(20, "Intro", "1", "2")];
+        assert_eq!(
+            align_tracks(&tracks, &candidates, 70),
+            vec![Some((20, 100)), Some((10, 100))]
+        );
+    }
+
+    #[test]
+    fn align_tracks_without_numbers() {
+        let tracks = vec![
+            track("/music/a/1.flac", numbered("Second Song", "", "")),
+            track("/music/a/First Song.flac", numbered("", "", "")),
+        ];
+        let candidates = vec![
+            candidate(10, "First Song", "1", "1"),
+            candidate(20, "Second Song", "2", "1"),
+        ];
+        assert_eq!(
+            align_tracks(&tracks, &candidates, 70),
+            vec![Some((20, 100)), Some((10, 100))]
+        );
+    }
+
+    #[test]
+    fn align_tracks_with_more_files_than_album_tracks() {
+        let tracks = vec![
+            track("/music/a/1.flac", numbered("Song", "1", "")),
+            track("/music/a/2.flac", numbered("Song", "", "")),
+            track("/music/a/3.flac", numbered("Bonus", "", "")),
+        ];
+        let candidates = vec![candidate(10, "Song", "1", "1")];
+        assert_eq!(
+            align_tracks(&tracks, &candidates, 70),
+            vec![Some((10, 100)), None, None]
+        );
+    }
+}
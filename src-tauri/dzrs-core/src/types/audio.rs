@@ -0,0 +1,1156 @@
+use crate::config::DzrsConfigurationParsed;
+use crate::types::tags::{set_id3v2_tags, set_ilst_tags, set_vorbis_tags, DzrsTrackObjectPicture, DzrsTrackObjectTags};
+
+use base64::{engine::general_purpose, Engine as _};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use lofty::flac::FlacFile;
+use lofty::id3::v2::{FrameValue, Id3v2Tag};
+use lofty::mp4::{Atom, AtomData, AtomIdent, Ilst, Mp4File};
+use lofty::mpeg::MpegFile;
+use lofty::ogg::{OggPictureStorage, OpusFile, VorbisComments, VorbisFile};
+use lofty::{AudioFile, FileType, MimeType, ParseOptions, Picture, PictureInformation, PictureType, Probe};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::{File, FileTimes, OpenOptions};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+// Suffix of the temporary files written by save_safely, these are always ignored when scanning
+pub const TEMP_FILE_SUFFIX: &str = ".dzrs-tmp";
+
+// Opens a file as a specific DzrsAudioFile implementation
+type DzrsAudioFileOpener = fn(&mut BufReader<File>) -> Result<Box<dyn DzrsAudioFile>, String>;
+
+// Every supported container, files are matched against it using the FileType detected from their content
+// adding a format only requires implementing DzrsAudioFile and registering it here
+const AUDIO_FILE_REGISTRY: [(FileType, DzrsAudioFileOpener); 5] = [
+    (FileType::Flac, open_as::<FlacFile>),
+    (FileType::Mpeg, open_as::<MpegFile>),
+    (FileType::Mp4, open_as::<Mp4File>),
+    (FileType::Vorbis, open_as::<VorbisFile>),
+    (FileType::Opus, open_as::<OpusFile>),
+];
+
+// Common interface over the supported audio files, tags are always exchanged as DzrsTrackObjectTags
+pub trait DzrsAudioFile {
+    fn file_type(&self) -> FileType;
+    // Files without a tag return empty tags, one gets created by write_tags
+    fn read_tags(&self, config: &DzrsConfigurationParsed) -> DzrsTrackObjectTags;
+    fn read_properties(&self) -> DzrsTrackObjectProperties;
+    // Only updates the in-memory tag, save has to be called to write it into a file
+    fn write_tags(&mut self, tags: &DzrsTrackObjectTags, conf: &DzrsConfigurationParsed) -> Result<(), String>;
+    fn pictures(&self) -> Vec<Picture>;
+    // Replaces every picture of the file, in the given order
+    fn set_pictures(&mut self, pictures: Vec<Picture>) -> Result<(), String>;
+    // Writes the file in place, see save_safely
+    fn save(&self, path: &Path) -> Result<(), String>;
+    // Tag of the file at path as stored, see DzrsRawTag
+    fn read_raw_tag(&self, path: &Path) -> Result<DzrsRawTag, String>;
+    // Writes back a tag read by read_raw_tag along with the given pictures, as they were and regardless of the config
+    fn save_raw_tag(
+        &mut self,
+        path: &Path,
+        raw: &DzrsRawTag,
+        pictures: Vec<Picture>,
+        keep_mtime: bool,
+    ) -> Result<(), String>;
+
+    // Writes the file into a temporary copy next to it, synced to disk and checked to parse with unchanged audio
+    // before renaming it over the original, so an interrupted write never leaves a corrupted file
+    // permissions, ownership and access time are kept, the modification time as well with keep_mtime
+    fn save_safely(&self, path: &Path, keep_mtime: bool) -> Result<(), String> {
+        save_with(path, self.file_type(), keep_mtime, |tmp_path| self.save(tmp_path))
+    }
+
+    fn read_pictures(&self) -> Vec<DzrsTrackObjectPicture> {
+        self.pictures()
+            .into_iter()
+            .filter_map(|p| {
+                PictureInformation::from_picture(&p)
+                    .ok()
+                    .map(|info| DzrsTrackObjectPicture::new(&(p, info)))
+            })
+            .collect()
+    }
+
+    // Embeds the cover of the given tags as FrontCover following tag_cover_policy
+    // "replace" removes the existing FrontCover, "add" keeps it and "keep" skips files that already have one
+    fn write_cover(&mut self, tags: &DzrsTrackObjectTags, conf: &DzrsConfigurationParsed) -> Result<(), String> {
+        let cover = match &tags.cover {
+            Some(cover) => cover.to_front_cover()?,
+            None => return Ok(()),
+        };
+        let mut pictures = self.pictures();
+        let has_front_cover = pictures.iter().any(|p| p.pic_type() == PictureType::CoverFront);
+        match conf.tag_cover_policy.as_str() {
+            "keep" if has_front_cover => return Ok(()),
+            "replace" => pictures.retain(|p| p.pic_type() != PictureType::CoverFront),
+            _ => (),
+        };
+        pictures.push(cover);
+        self.set_pictures(pictures)
+    }
+
+    // Adds a picture, replacing the existing ones of the same type
+    fn insert_picture(&mut self, picture: Picture) -> Result<(), String> {
+        let mut pictures = self.pictures();
+        pictures.retain(|p| p.pic_type() != picture.pic_type());
+        pictures.push(picture);
+        self.set_pictures(pictures)
+    }
+
+    fn remove_picture_type(&mut self, pic_type: PictureType) -> Result<(), String> {
+        let mut pictures = self.pictures();
+        pictures.retain(|p| p.pic_type() != pic_type);
+        self.set_pictures(pictures)
+    }
+
+    // Downscales and re-encodes pictures larger than max_size, files without oversized pictures are left untouched
+    // pictures which cannot be decoded, e.g. TIFF or corrupted ones, are kept as they are
+    fn resize_pictures(&mut self, max_size: u32, quality: u8) -> Result<(), String> {
+        let mut resized = false;
+        let mut pictures = Vec::new();
+        for picture in self.pictures() {
+            match resize_picture(&picture, max_size, quality) {
+                Ok(Some(p)) => {
+                    resized = true;
+                    pictures.push(p);
+                }
+                Ok(None) | Err(_) => pictures.push(picture),
+            };
+        }
+        match resized {
+            true => self.set_pictures(pictures),
+            false => Ok(()),
+        }
+    }
+}
+
+// Tag of a file as stored, backed up before writing into the file so it can be written back exactly as it was
+// pictures are backed up on their own, except for ID3v2 where they are part of the tag bytes
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", tag = "format")]
+pub enum DzrsRawTag {
+    // Every field of the vorbis comments in order, along with the vendor string
+    Vorbis {
+        vendor: String,
+        items: Vec<(String, String)>,
+    },
+    // Bytes of the ID3v2 tag found at the start of the file, empty when there is none
+    Id3v2 {
+        b64: String,
+    },
+    // Every atom of the ilst but covr
+    Ilst {
+        atoms: Vec<DzrsRawAtom>,
+    },
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DzrsRawAtom {
+    // Fourcc bytes, freeform atoms have none and are identified by their mean and name
+    fourcc: Option<[u8; 4]>,
+    mean: String,
+    name: String,
+    values: Vec<DzrsRawAtomData>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", tag = "type", content = "value")]
+enum DzrsRawAtomData {
+    Utf8(String),
+    Utf16(String),
+    SignedInteger(i32),
+    UnsignedInteger(u32),
+    Bool(bool),
+    // e.g. trkn and disk, kept as their raw bytes
+    Unknown { code: u32, b64: String },
+}
+
+impl DzrsRawAtom {
+    // None for atoms only holding pictures
+    fn new(atom: &Atom<'_>) -> Option<Self> {
+        let (fourcc, mean, name) = match atom.ident() {
+            AtomIdent::Fourcc(f) => (Some(*f), String::new(), String::new()),
+            AtomIdent::Freeform { mean, name } => (None, mean.to_string(), name.to_string()),
+        };
+        let values: Vec<DzrsRawAtomData> = atom
+            .data()
+            .filter_map(|d| match d {
+                AtomData::UTF8(v) => Some(DzrsRawAtomData::Utf8(v.clone())),
+                AtomData::UTF16(v) => Some(DzrsRawAtomData::Utf16(v.clone())),
+                AtomData::SignedInteger(i) => Some(DzrsRawAtomData::SignedInteger(*i)),
+                AtomData::UnsignedInteger(i) => Some(DzrsRawAtomData::UnsignedInteger(*i)),
+                AtomData::Bool(b) => Some(DzrsRawAtomData::Bool(*b)),
+                AtomData::Unknown { code, data } => Some(DzrsRawAtomData::Unknown {
+                    code: *code,
+                    b64: general_purpose::STANDARD.encode(data),
+                }),
+                // Pictures are backed up on their own
+                _ => None,
+            })
+            .collect();
+        match values.is_empty() {
+            true => None,
+            false => Some(Self {
+                fourcc,
+                mean,
+                name,
+                values,
+            }),
+        }
+    }
+
+    fn to_atom(&self) -> Result<Atom<'static>, String> {
+        let ident = match self.fourcc {
+            Some(f) => AtomIdent::Fourcc(f),
+            None => AtomIdent::Freeform {
+                mean: Cow::Owned(self.mean.clone()),
+                name: Cow::Owned(self.name.clone()),
+            },
+        };
+        let mut data = Vec::new();
+        for value in &self.values {
+            data.push(match value {
+                DzrsRawAtomData::Utf8(v) => AtomData::UTF8(v.clone()),
+                DzrsRawAtomData::Utf16(v) => AtomData::UTF16(v.clone()),
+                DzrsRawAtomData::SignedInteger(i) => AtomData::SignedInteger(*i),
+                DzrsRawAtomData::UnsignedInteger(i) => AtomData::UnsignedInteger(*i),
+                DzrsRawAtomData::Bool(b) => AtomData::Bool(*b),
+                DzrsRawAtomData::Unknown { code, b64 } => AtomData::Unknown {
+                    code: *code,
+                    data: general_purpose::STANDARD.decode(b64).map_err(|err| err.to_string())?,
+                },
+            });
+        }
+        Atom::from_collection(ident, data).ok_or_else(|| "Cannot restore an empty atom".to_string())
+    }
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DzrsTrackObjectProperties {
+    pub duration: u64,
+    pub bitrate: u32,
+    pub sample_rate: u32,
+    pub bit_depth: Option<u8>,
+    pub channels: u8,
+}
+
+// Detects the container of a file by looking at its content, the extension is never considered
+pub fn detect_file_type<P: AsRef<Path>>(path: P) -> Result<FileType, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let probe = Probe::new(BufReader::new(file))
+        .guess_file_type()
+        .map_err(|err| err.to_string())?;
+    probe.file_type().ok_or_else(|| "Unknown file type".to_string())
+}
+
+// Opens a file with the DzrsAudioFile implementation registered for its detected container
+pub fn open_audio_file<P: AsRef<Path>>(path: P) -> Result<Box<dyn DzrsAudioFile>, String> {
+    let path = path.as_ref();
+    let file_type = detect_file_type(path)?;
+    let opener = match AUDIO_FILE_REGISTRY.iter().find(|(ft, _)| ft == &file_type) {
+        Some((_, opener)) => opener,
+        None => return Err(format!("Unsupported file type {:?} for {}", file_type, path.display())),
+    };
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    opener(&mut BufReader::new(file))
+}
+
+// Maps a picture type name, as displayed by DzrsTrackObjectPicture, back to a PictureType
+pub fn picture_type_from_str(value: &str) -> Result<PictureType, String> {
+    (0..=20)
+        .map(PictureType::from_u8)
+        .find(|t| format!("{:?}", t) == value)
+        .ok_or_else(|| format!("Invalid picture type {}", value))
+}
+
+// Downscales a picture to fit within max_size and re-encodes it as JPEG, returns None when it already fits
+pub fn resize_picture(picture: &Picture, max_size: u32, quality: u8) -> Result<Option<Picture>, String> {
+    let img = image::load_from_memory(picture.data()).map_err(|err| err.to_string())?;
+    if img.width() <= max_size && img.height() <= max_size {
+        return Ok(None);
+    }
+    let img = img.resize(max_size, max_size, FilterType::Lanczos3);
+    let data = encode_jpeg(&img, quality)?;
+    Ok(Some(Picture::new_unchecked(
+        picture.pic_type(),
+        MimeType::Jpeg,
+        picture.description().map(|d| d.to_string()),
+        data,
+    )))
+}
+
+// JPEG has no alpha channel, so pictures are always converted to rgb before encoding
+pub fn encode_jpeg(img: &image::DynamicImage, quality: u8) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    JpegEncoder::new_with_quality(&mut data, quality)
+        .encode_image(&img.to_rgb8())
+        .map_err(|err| err.to_string())?;
+    Ok(data)
+}
+
+// Hidden file in the same directory, so the final rename never crosses filesystems
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}{}", name, TEMP_FILE_SUFFIX))
+}
+
+// Writes a file through a temporary copy, see save_safely, write gets the path of the copy to write into
+fn save_with(
+    path: &Path,
+    file_type: FileType,
+    keep_mtime: bool,
+    write: impl FnOnce(&Path) -> Result<(), String>,
+) -> Result<(), String> {
+    // Symbolic links are followed, otherwise the link itself would be replaced
+    let path = path.canonicalize().map_err(|err| err.to_string())?;
+    let tmp_path = temp_path(&path);
+    let result = write_temp(&path, &tmp_path, file_type, keep_mtime, write)
+        .and_then(|_| std::fs::rename(&tmp_path, &path).map_err(|err| err.to_string()));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    };
+    result?;
+    sync_parent_dir(&path);
+    Ok(())
+}
+
+fn write_temp(
+    path: &Path,
+    tmp_path: &Path,
+    file_type: FileType,
+    keep_mtime: bool,
+    write: impl FnOnce(&Path) -> Result<(), String>,
+) -> Result<(), String> {
+    let metadata = std::fs::metadata(path).map_err(|err| err.to_string())?;
+    let audio = audio_md5(path, file_type)?;
+    // The copy keeps the permissions of the original
+    std::fs::copy(path, tmp_path).map_err(|err| err.to_string())?;
+    write(tmp_path)?;
+
+    let tmp = OpenOptions::new()
+        .write(true)
+        .open(tmp_path)
+        .map_err(|err| err.to_string())?;
+    let mut times = FileTimes::new().set_accessed(metadata.accessed().map_err(|err| err.to_string())?);
+    if keep_mtime {
+        times = times.set_modified(metadata.modified().map_err(|err| err.to_string())?);
+    };
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::FileTimesExt;
+        times = times.set_created(metadata.created().map_err(|err| err.to_string())?);
+    }
+    tmp.set_times(times).map_err(|err| err.to_string())?;
+    tmp.sync_all().map_err(|err| err.to_string())?;
+    drop(tmp);
+    // Changing the owner requires privileges, files owned by the user are left as they are
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let _ = std::os::unix::fs::chown(tmp_path, Some(metadata.uid()), Some(metadata.gid()));
+    }
+
+    let saved = open_audio_file(tmp_path).map_err(|err| format!("Cannot read back {}, {}", path.display(), err))?;
+    if saved.file_type() != file_type || audio_md5(tmp_path, saved.file_type())? != audio {
+        return Err(format!(
+            "Audio data of {} changed while saving, the file was left untouched",
+            path.display()
+        ));
+    };
+    Ok(())
+}
+
+// The rename itself is only durable once the directory is synced, not supported on windows
+fn sync_parent_dir(path: &Path) {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let _ = File::open(dir).and_then(|d| d.sync_all());
+    };
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+// MD5 of the audio payload of a file, every byte holding tags is left out so that writing tags never changes it
+fn audio_md5(path: &Path, file_type: FileType) -> Result<md5::Digest, String> {
+    let data = std::fs::read(path).map_err(|err| err.to_string())?;
+    let invalid = || format!("Cannot find the audio data of {}", path.display());
+    let mut ctx = md5::Context::new();
+    match file_type {
+        FileType::Flac => ctx.consume(flac_frames(&data).ok_or_else(invalid)?),
+        FileType::Mpeg => ctx.consume(mpeg_frames(&data)),
+        FileType::Mp4 => {
+            for chunk in mp4_media_data(&data).ok_or_else(invalid)? {
+                ctx.consume(chunk);
+            }
+        }
+        FileType::Vorbis | FileType::Opus => {
+            for chunk in ogg_audio_pages(&data).ok_or_else(invalid)? {
+                ctx.consume(chunk);
+            }
+        }
+        _ => return Err(format!("Unsupported file type {:?} for {}", file_type, path.display())),
+    };
+    Ok(ctx.compute())
+}
+
+// Length of the ID3v2 tag found at the start of the data, if any
+fn id3v2_len(data: &[u8]) -> usize {
+    if data.len() < 10 || &data[..3] != b"ID3" {
+        return 0;
+    };
+    let size = data[6..10]
+        .iter()
+        .fold(0usize, |acc, b| (acc << 7) | (*b & 0x7f) as usize);
+    let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+    10 + size + footer
+}
+
+fn read_u32_be(data: &[u8], pos: usize) -> Option<usize> {
+    let bytes: [u8; 4] = data.get(pos..pos + 4)?.try_into().ok()?;
+    Some(u32::from_be_bytes(bytes) as usize)
+}
+
+// Frames following the metadata blocks, each block header holds a last block flag and a 24 bits length
+fn flac_frames(data: &[u8]) -> Option<&[u8]> {
+    let mut pos = id3v2_len(data);
+    if data.get(pos..pos + 4)? != b"fLaC" {
+        return None;
+    };
+    pos += 4;
+    loop {
+        let header = read_u32_be(data, pos)?;
+        pos += 4 + (header & 0xff_ffff);
+        if header & 0x8000_0000 != 0 {
+            break;
+        };
+    }
+    data.get(pos..)
+}
+
+// Frames between the ID3v2 tag and the trailing ID3v1 and APEv2 tags
+fn mpeg_frames(data: &[u8]) -> &[u8] {
+    let start = id3v2_len(data).min(data.len());
+    let mut end = data.len();
+    if end >= start + 128 && &data[end - 128..end - 125] == b"TAG" {
+        end -= 128;
+    };
+    if end >= start + 32 && &data[end - 32..end - 24] == b"APETAGEX" {
+        let size = u32::from_le_bytes(data[end - 20..end - 16].try_into().unwrap_or_default()) as usize;
+        let flags = u32::from_le_bytes(data[end - 12..end - 8].try_into().unwrap_or_default());
+        let header = if flags & 0x8000_0000 != 0 { 32 } else { 0 };
+        end = end.saturating_sub(size + header).max(start);
+    };
+    &data[start..end]
+}
+
+// Content of the top level mdat atoms, tags are stored within moov
+fn mp4_media_data(data: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut chunks = Vec::new();
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let (header, size) = match read_u32_be(data, pos)? {
+            0 => (8, data.len() - pos),
+            1 => {
+                let bytes: [u8; 8] = data.get(pos + 8..pos + 16)?.try_into().ok()?;
+                (16, u64::from_be_bytes(bytes) as usize)
+            }
+            size => (8, size),
+        };
+        if size < header || pos + size > data.len() {
+            return None;
+        };
+        if &data[pos + 4..pos + 8] == b"mdat" {
+            chunks.push(&data[pos + header..pos + size]);
+        };
+        pos += size;
+    }
+    Some(chunks)
+}
+
+// Bodies of the pages past the headers, header pages have a granule position of 0 while the page sequence numbers
+// and checksums of the audio pages change when the comment header grows, so only their bodies are compared
+fn ogg_audio_pages(data: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut chunks = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        if data.get(pos..pos + 4)? != b"OggS" {
+            return None;
+        };
+        let granule = i64::from_le_bytes(data.get(pos + 6..pos + 14)?.try_into().ok()?);
+        let segments = *data.get(pos + 26)? as usize;
+        let body_len: usize = data
+            .get(pos + 27..pos + 27 + segments)?
+            .iter()
+            .map(|s| *s as usize)
+            .sum();
+        let body = pos + 27 + segments;
+        if granule != 0 {
+            chunks.push(data.get(body..body + body_len)?);
+        };
+        pos = body + body_len;
+    }
+    Some(chunks)
+}
+
+fn open_as<F: AudioFile + DzrsAudioFile + 'static>(
+    reader: &mut BufReader<File>,
+) -> Result<Box<dyn DzrsAudioFile>, String> {
+    match F::read_from(reader, ParseOptions::default()) {
+        Ok(f) => Ok(Box::new(f)),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+const RAW_TAG_MISMATCH: &str = "The backed up tag does not match the format of the file";
+
+fn raw_vorbis(vorbis: Option<&VorbisComments>) -> DzrsRawTag {
+    DzrsRawTag::Vorbis {
+        vendor: vorbis.map(|v| v.vendor().to_string()).unwrap_or_default(),
+        items: vorbis
+            .map(|v| v.items().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+            .unwrap_or_default(),
+    }
+}
+
+fn vorbis_from_raw(raw: &DzrsRawTag) -> Result<VorbisComments, String> {
+    match raw {
+        DzrsRawTag::Vorbis { vendor, items } => {
+            let mut vorbis = VorbisComments::default();
+            vorbis.set_vendor(vendor.clone());
+            for (key, value) in items {
+                vorbis.push(key.clone(), value.clone());
+            }
+            Ok(vorbis)
+        }
+        _ => Err(RAW_TAG_MISMATCH.into()),
+    }
+}
+
+// FLAC picture blocks and METADATA_BLOCK_PICTURE fields of ogg files share the same storage interface
+fn set_ogg_pictures<S: OggPictureStorage>(storage: &mut S, pictures: Vec<Picture>) -> Result<(), String> {
+    let pic_types: Vec<PictureType> = storage.pictures().iter().map(|(p, _)| p.pic_type()).collect();
+    for pic_type in pic_types {
+        storage.remove_picture_type(pic_type);
+    }
+    for picture in pictures {
+        let info = PictureInformation::from_picture(&picture).map_err(|err| err.to_string())?;
+        storage
+            .insert_picture(picture, Some(info))
+            .map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+impl DzrsAudioFile for FlacFile {
+    fn file_type(&self) -> FileType {
+        FileType::Flac
+    }
+
+    fn read_tags(&self, _config: &DzrsConfigurationParsed) -> DzrsTrackObjectTags {
+        match self.vorbis_comments() {
+            Some(vorbis) => DzrsTrackObjectTags::new(vorbis),
+            None => DzrsTrackObjectTags::default(),
+        }
+    }
+
+    fn read_properties(&self) -> DzrsTrackObjectProperties {
+        let p = self.properties();
+        DzrsTrackObjectProperties {
+            duration: p.duration().as_secs(),
+            bitrate: p.audio_bitrate(),
+            sample_rate: p.sample_rate(),
+            bit_depth: Some(p.bit_depth()),
+            channels: p.channels(),
+        }
+    }
+
+    fn write_tags(&mut self, tags: &DzrsTrackObjectTags, conf: &DzrsConfigurationParsed) -> Result<(), String> {
+        if self.vorbis_comments().is_none() {
+            self.set_vorbis_comments(VorbisComments::default());
+        };
+        match self.vorbis_comments_mut() {
+            Some(vorbis) => {
+                set_vorbis_tags(tags, vorbis, conf);
+                Ok(())
+            }
+            None => Err("Vorbis Comments not found".to_string()),
+        }
+    }
+
+    // FLAC pictures are stored in their own metadata blocks, outside of the vorbis comments
+    fn pictures(&self) -> Vec<Picture> {
+        OggPictureStorage::pictures(self)
+            .iter()
+            .map(|(p, _)| p.clone())
+            .collect()
+    }
+
+    fn set_pictures(&mut self, pictures: Vec<Picture>) -> Result<(), String> {
+        set_ogg_pictures(self, pictures)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        self.save_to_path(path).map_err(|err| err.to_string())
+    }
+
+    fn read_raw_tag(&self, _path: &Path) -> Result<DzrsRawTag, String> {
+        Ok(raw_vorbis(self.vorbis_comments()))
+    }
+
+    fn save_raw_tag(
+        &mut self,
+        path: &Path,
+        raw: &DzrsRawTag,
+        pictures: Vec<Picture>,
+        keep_mtime: bool,
+    ) -> Result<(), String> {
+        self.set_vorbis_comments(vorbis_from_raw(raw)?);
+        self.set_pictures(pictures)?;
+        self.save_safely(path, keep_mtime)
+    }
+}
+
+impl DzrsAudioFile for MpegFile {
+    fn file_type(&self) -> FileType {
+        FileType::Mpeg
+    }
+
+    fn read_tags(&self, config: &DzrsConfigurationParsed) -> DzrsTrackObjectTags {
+        match self.id3v2() {
+            Some(id3) => DzrsTrackObjectTags::from_id3v2(id3, config),
+            None => DzrsTrackObjectTags::default(),
+        }
+    }
+
+    fn read_properties(&self) -> DzrsTrackObjectProperties {
+        let p = self.properties();
+        DzrsTrackObjectProperties {
+            duration: p.duration().as_secs(),
+            bitrate: p.audio_bitrate(),
+            sample_rate: p.sample_rate(),
+            bit_depth: None,
+            channels: p.channels(),
+        }
+    }
+
+    fn write_tags(&mut self, tags: &DzrsTrackObjectTags, conf: &DzrsConfigurationParsed) -> Result<(), String> {
+        if self.id3v2().is_none() {
+            self.set_id3v2(Id3v2Tag::default());
+        };
+        match self.id3v2_mut() {
+            Some(id3) => {
+                set_id3v2_tags(tags, id3, conf);
+                Ok(())
+            }
+            None => Err("ID3v2 tag not found".to_string()),
+        }
+    }
+
+    fn pictures(&self) -> Vec<Picture> {
+        match self.id3v2() {
+            Some(id3) => id3
+                .iter()
+                .filter_map(|f| match f.content() {
+                    FrameValue::Picture(p) => Some(p.picture.clone()),
+                    _ => None,
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn set_pictures(&mut self, pictures: Vec<Picture>) -> Result<(), String> {
+        if self.id3v2().is_none() {
+            self.set_id3v2(Id3v2Tag::default());
+        };
+        match self.id3v2_mut() {
+            Some(id3) => {
+                let _ = id3.remove("APIC");
+                for picture in pictures {
+                    id3.insert_picture(picture);
+                }
+                Ok(())
+            }
+            None => Err("ID3v2 tag not found".to_string()),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        self.save_to_path(path).map_err(|err| err.to_string())
+    }
+
+    // The ID3v2 tag is copied back byte for byte, its pictures included, so the pictures are left alone
+    fn read_raw_tag(&self, path: &Path) -> Result<DzrsRawTag, String> {
+        let data = std::fs::read(path).map_err(|err| err.to_string())?;
+        let len = id3v2_len(&data).min(data.len());
+        Ok(DzrsRawTag::Id3v2 {
+            b64: general_purpose::STANDARD.encode(&data[..len]),
+        })
+    }
+
+    fn save_raw_tag(
+        &mut self,
+        path: &Path,
+        raw: &DzrsRawTag,
+        _pictures: Vec<Picture>,
+        keep_mtime: bool,
+    ) -> Result<(), String> {
+        let tag = match raw {
+            DzrsRawTag::Id3v2 { b64 } => general_purpose::STANDARD.decode(b64).map_err(|err| err.to_string())?,
+            _ => return Err(RAW_TAG_MISMATCH.into()),
+        };
+        save_with(path, FileType::Mpeg, keep_mtime, |tmp_path| {
+            let data = std::fs::read(tmp_path).map_err(|err| err.to_string())?;
+            let audio_start = id3v2_len(&data).min(data.len());
+            let mut restored = tag;
+            restored.extend_from_slice(&data[audio_start..]);
+            std::fs::write(tmp_path, restored).map_err(|err| err.to_string())
+        })
+    }
+}
+
+impl DzrsAudioFile for Mp4File {
+    fn file_type(&self) -> FileType {
+        FileType::Mp4
+    }
+
+    fn read_tags(&self, config: &DzrsConfigurationParsed) -> DzrsTrackObjectTags {
+        match self.ilst() {
+            Some(ilst) => DzrsTrackObjectTags::from_ilst(ilst, config),
+            None => DzrsTrackObjectTags::default(),
+        }
+    }
+
+    fn read_properties(&self) -> DzrsTrackObjectProperties {
+        let p = self.properties();
+        DzrsTrackObjectProperties {
+            duration: p.duration().as_secs(),
+            bitrate: p.audio_bitrate(),
+            sample_rate: p.sample_rate(),
+            bit_depth: p.bit_depth(),
+            channels: p.channels(),
+        }
+    }
+
+    fn write_tags(&mut self, tags: &DzrsTrackObjectTags, conf: &DzrsConfigurationParsed) -> Result<(), String> {
+        if self.ilst().is_none() {
+            self.set_ilst(Ilst::default());
+        };
+        match self.ilst_mut() {
+            Some(ilst) => {
+                set_ilst_tags(tags, ilst, conf);
+                Ok(())
+            }
+            None => Err("Ilst not found".to_string()),
+        }
+    }
+
+    // covr pictures have no type, they are all handled as FrontCover
+    fn pictures(&self) -> Vec<Picture> {
+        match self.ilst().and_then(|ilst| ilst.get(&AtomIdent::Fourcc(*b"covr"))) {
+            Some(covr) => covr
+                .data()
+                .filter_map(|d| match d {
+                    AtomData::Picture(p) => {
+                        let mut p = p.clone();
+                        p.set_pic_type(PictureType::CoverFront);
+                        Some(p)
+                    }
+                    _ => None,
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn set_pictures(&mut self, pictures: Vec<Picture>) -> Result<(), String> {
+        if self.ilst().is_none() {
+            self.set_ilst(Ilst::default());
+        };
+        match self.ilst_mut() {
+            Some(ilst) => {
+                let _ = ilst.remove(&AtomIdent::Fourcc(*b"covr"));
+                for picture in pictures {
+                    ilst.insert_picture(picture);
+                }
+                Ok(())
+            }
+            None => Err("Ilst not found".to_string()),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        self.save_to_path(path).map_err(|err| err.to_string())
+    }
+
+    fn read_raw_tag(&self, _path: &Path) -> Result<DzrsRawTag, String> {
+        let atoms = match self.ilst() {
+            Some(ilst) => ilst
+                .iter()
+                .filter(|atom| atom.ident() != &AtomIdent::Fourcc(*b"covr"))
+                .filter_map(DzrsRawAtom::new)
+                .collect(),
+            None => Vec::new(),
+        };
+        Ok(DzrsRawTag::Ilst { atoms })
+    }
+
+    fn save_raw_tag(
+        &mut self,
+        path: &Path,
+        raw: &DzrsRawTag,
+        pictures: Vec<Picture>,
+        keep_mtime: bool,
+    ) -> Result<(), String> {
+        let atoms = match raw {
+            DzrsRawTag::Ilst { atoms } => atoms,
+            _ => return Err(RAW_TAG_MISMATCH.into()),
+        };
+        let mut ilst = Ilst::default();
+        for atom in atoms {
+            ilst.insert(atom.to_atom()?);
+        }
+        self.set_ilst(ilst);
+        self.set_pictures(pictures)?;
+        self.save_safely(path, keep_mtime)
+    }
+}
+
+// Ogg files only get their comment header packet rewritten on save, so the audio is never re-encoded
+// pictures are stored within the comments as METADATA_BLOCK_PICTURE fields
+impl DzrsAudioFile for VorbisFile {
+    fn file_type(&self) -> FileType {
+        FileType::Vorbis
+    }
+
+    fn read_tags(&self, _config: &DzrsConfigurationParsed) -> DzrsTrackObjectTags {
+        DzrsTrackObjectTags::new(self.vorbis_comments())
+    }
+
+    fn read_properties(&self) -> DzrsTrackObjectProperties {
+        let p = self.properties();
+        DzrsTrackObjectProperties {
+            duration: p.duration().as_secs(),
+            bitrate: p.audio_bitrate(),
+            sample_rate: p.sample_rate(),
+            bit_depth: None,
+            channels: p.channels(),
+        }
+    }
+
+    fn write_tags(&mut self, tags: &DzrsTrackObjectTags, conf: &DzrsConfigurationParsed) -> Result<(), String> {
+        set_vorbis_tags(tags, self.vorbis_comments_mut(), conf);
+        Ok(())
+    }
+
+    fn pictures(&self) -> Vec<Picture> {
+        self.vorbis_comments()
+            .pictures()
+            .iter()
+            .map(|(p, _)| p.clone())
+            .collect()
+    }
+
+    fn set_pictures(&mut self, pictures: Vec<Picture>) -> Result<(), String> {
+        set_ogg_pictures(self.vorbis_comments_mut(), pictures)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        self.save_to_path(path).map_err(|err| err.to_string())
+    }
+
+    fn read_raw_tag(&self, _path: &Path) -> Result<DzrsRawTag, String> {
+        Ok(raw_vorbis(Some(self.vorbis_comments())))
+    }
+
+    fn save_raw_tag(
+        &mut self,
+        path: &Path,
+        raw: &DzrsRawTag,
+        pictures: Vec<Picture>,
+        keep_mtime: bool,
+    ) -> Result<(), String> {
+        *self.vorbis_comments_mut() = vorbis_from_raw(raw)?;
+        self.set_pictures(pictures)?;
+        self.save_safely(path, keep_mtime)
+    }
+}
+
+impl DzrsAudioFile for OpusFile {
+    fn file_type(&self) -> FileType {
+        FileType::Opus
+    }
+
+    fn read_tags(&self, _config: &DzrsConfigurationParsed) -> DzrsTrackObjectTags {
+        DzrsTrackObjectTags::new(self.vorbis_comments())
+    }
+
+    fn read_properties(&self) -> DzrsTrackObjectProperties {
+        let p = self.properties();
+        DzrsTrackObjectProperties {
+            duration: p.duration().as_secs(),
+            bitrate: p.audio_bitrate(),
+            sample_rate: p.input_sample_rate(),
+            bit_depth: None,
+            channels: p.channels(),
+        }
+    }
+
+    fn write_tags(&mut self, tags: &DzrsTrackObjectTags, conf: &DzrsConfigurationParsed) -> Result<(), String> {
+        set_vorbis_tags(tags, self.vorbis_comments_mut(), conf);
+        Ok(())
+    }
+
+    fn pictures(&self) -> Vec<Picture> {
+        self.vorbis_comments()
+            .pictures()
+            .iter()
+            .map(|(p, _)| p.clone())
+            .collect()
+    }
+
+    fn set_pictures(&mut self, pictures: Vec<Picture>) -> Result<(), String> {
+        set_ogg_pictures(self.vorbis_comments_mut(), pictures)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        self.save_to_path(path).map_err(|err| err.to_string())
+    }
+
+    fn read_raw_tag(&self, _path: &Path) -> Result<DzrsRawTag, String> {
+        Ok(raw_vorbis(Some(self.vorbis_comments())))
+    }
+
+    fn save_raw_tag(
+        &mut self,
+        path: &Path,
+        raw: &DzrsRawTag,
+        pictures: Vec<Picture>,
+        keep_mtime: bool,
+    ) -> Result<(), String> {
+        *self.vorbis_comments_mut() = vorbis_from_raw(raw)?;
+        self.set_pictures(pictures)?;
+        self.save_safely(path, keep_mtime)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Stands in for encoded audio, the frames are never decoded
+    pub(crate) const FRAMES: &[u8] = &[
+        0xFF, 0xF8, 0x69, 0x18, 0x00, 0x00, 0xBF, 0x03, 0x58, 0xFD, 0x03, 0x12, 0x8B,
+    ];
+
+    // FLAC holding a second of 44.1 kHz stereo audio, with an empty vorbis comment of the given vendor
+    pub(crate) fn flac_bytes(vendor: &str, frames: &[u8]) -> Vec<u8> {
+        let mut data = b"fLaC".to_vec();
+        data.extend([0x00, 0x00, 0x00, 0x22]);
+        data.extend([0x10, 0x00, 0x10, 0x00]);
+        data.extend([0u8; 6]);
+        data.extend((44100u64 << 44 | 1 << 41 | 15 << 36 | 44100).to_be_bytes());
+        data.extend([0u8; 16]);
+        data.push(0x84);
+        data.extend(&((vendor.len() + 8) as u32).to_be_bytes()[1..]);
+        data.extend((vendor.len() as u32).to_le_bytes());
+        data.extend(vendor.as_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(frames);
+        data
+    }
+
+    fn id3v2_bytes(len: usize) -> Vec<u8> {
+        let mut data = b"ID3\x04\x00\x00".to_vec();
+        data.extend([
+            (len >> 21) as u8 & 0x7f,
+            (len >> 14) as u8 & 0x7f,
+            (len >> 7) as u8 & 0x7f,
+            len as u8 & 0x7f,
+        ]);
+        data.extend(vec![0u8; len]);
+        data
+    }
+
+    fn ape_bytes(items: &[u8], header: bool) -> Vec<u8> {
+        let block = |flags: u32| {
+            let mut block = b"APETAGEX".to_vec();
+            block.extend(2000u32.to_le_bytes());
+            block.extend(((items.len() + 32) as u32).to_le_bytes());
+            block.extend(1u32.to_le_bytes());
+            block.extend(flags.to_le_bytes());
+            block.extend([0u8; 8]);
+            block
+        };
+        let mut data = match header {
+            true => block(0xA000_0000),
+            false => Vec::new(),
+        };
+        data.extend(items);
+        data.extend(block(if header { 0x8000_0000 } else { 0 }));
+        data
+    }
+
+    fn atom(name: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend(name);
+        data.extend(body);
+        data
+    }
+
+    fn ogg_page(granule: i64, sequence: u32, checksum: u32, body: &[u8]) -> Vec<u8> {
+        let mut data = b"OggS\x00\x00".to_vec();
+        data.extend(granule.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        data.extend(sequence.to_le_bytes());
+        data.extend(checksum.to_le_bytes());
+        data.push(1);
+        data.push(body.len() as u8);
+        data.extend(body);
+        data
+    }
+
+    fn flipped(frames: &[u8]) -> Vec<u8> {
+        let mut frames = frames.to_vec();
+        frames[6] ^= 0x01;
+        frames
+    }
+
+    fn md5_of(data: &[u8], file_type: FileType) -> md5::Digest {
+        static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+        let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("dzrs-audio-md5-{}-{}", std::process::id(), n));
+        std::fs::write(&path, data).unwrap();
+        let md5 = audio_md5(&path, file_type);
+        std::fs::remove_file(&path).unwrap();
+        md5.unwrap()
+    }
+
+    #[test]
+    fn flac_frames_skip_every_metadata_block() {
+        assert_eq!(flac_frames(&flac_bytes("a", FRAMES)), Some(FRAMES));
+        assert_eq!(flac_frames(&flac_bytes("a much longer vendor", FRAMES)), Some(FRAMES));
+        let mut tagged = id3v2_bytes(20);
+        tagged.extend(flac_bytes("a", FRAMES));
+        assert_eq!(flac_frames(&tagged), Some(FRAMES));
+    }
+
+    #[test]
+    fn flac_frames_invalid() {
+        assert_eq!(flac_frames(b"OggS"), None);
+        assert_eq!(flac_frames(b"fLaC\x00\x00"), None);
+        let mut truncated = flac_bytes("a", &[]);
+        truncated.truncate(20);
+        assert_eq!(flac_frames(&truncated), None);
+    }
+
+    #[test]
+    fn mpeg_frames_skip_tags() {
+        let mut data = id3v2_bytes(20);
+        data.extend(FRAMES);
+        assert_eq!(mpeg_frames(&data), FRAMES);
+        let mut id3v1 = data.clone();
+        id3v1.extend(b"TAG");
+        id3v1.extend([0u8; 125]);
+        assert_eq!(mpeg_frames(&id3v1), FRAMES);
+        for header in [false, true] {
+            let mut ape = data.clone();
+            ape.extend(ape_bytes(b"ITEMS", header));
+            assert_eq!(mpeg_frames(&ape), FRAMES);
+            ape.extend(b"TAG");
+            ape.extend([0u8; 125]);
+            assert_eq!(mpeg_frames(&ape), FRAMES);
+        }
+        assert_eq!(mpeg_frames(FRAMES), FRAMES);
+        assert_eq!(mpeg_frames(b"ID3"), b"ID3");
+    }
+
+    #[test]
+    fn mp4_media_data_only_keeps_mdat() {
+        let mut data = atom(b"ftyp", b"M4A isom");
+        data.extend(atom(b"moov", b"tags"));
+        data.extend(atom(b"mdat", FRAMES));
+        assert_eq!(mp4_media_data(&data), Some(vec![FRAMES]));
+        // 64 bits size
+        let mut large = data[..data.len() - FRAMES.len() - 8].to_vec();
+        large.extend([0, 0, 0, 1]);
+        large.extend(b"mdat");
+        large.extend(((FRAMES.len() + 16) as u64).to_be_bytes());
+        large.extend(FRAMES);
+        assert_eq!(mp4_media_data(&large), Some(vec![FRAMES]));
+        // Size 0 extends to the end of the file
+        let mut last = data[..data.len() - FRAMES.len() - 8].to_vec();
+        last.extend([0, 0, 0, 0]);
+        last.extend(b"mdat");
+        last.extend(FRAMES);
+        assert_eq!(mp4_media_data(&last), Some(vec![FRAMES]));
+    }
+
+    #[test]
+    fn mp4_media_data_invalid() {
+        assert_eq!(mp4_media_data(&[0, 0, 0, 4, b'f', b't', b'y', b'p']), None);
+        assert_eq!(mp4_media_data(&[0, 0, 0, 64, b'm', b'd', b'a', b't', 0]), None);
+    }
+
+    #[test]
+    fn ogg_audio_pages_skip_header_pages() {
+        let mut data = ogg_page(0, 0, 1, b"header");
+        data.extend(ogg_page(0, 1, 2, b"comment"));
+        data.extend(ogg_page(1000, 2, 3, FRAMES));
+        assert_eq!(ogg_audio_pages(&data), Some(vec![FRAMES]));
+        assert_eq!(ogg_audio_pages(b"OggS\x00"), None);
+        assert_eq!(ogg_audio_pages(b"fLaC"), None);
+    }
+
+    #[test]
+    fn audio_md5_ignores_tags() {
+        assert_eq!(
+            md5_of(&flac_bytes("a", FRAMES), FileType::Flac),
+            md5_of(&flac_bytes("a much longer vendor", FRAMES), FileType::Flac)
+        );
+        let mut mpeg = id3v2_bytes(20);
+        mpeg.extend(FRAMES);
+        let mut retagged = id3v2_bytes(200);
+        retagged.extend(FRAMES);
+        retagged.extend(ape_bytes(b"ITEMS", true));
+        assert_eq!(md5_of(&mpeg, FileType::Mpeg), md5_of(&retagged, FileType::Mpeg));
+        let mp4 = [atom(b"moov", b"tags"), atom(b"mdat", FRAMES)].concat();
+        let retagged = [atom(b"moov", b"longer tags"), atom(b"free", b""), atom(b"mdat", FRAMES)].concat();
+        assert_eq!(md5_of(&mp4, FileType::Mp4), md5_of(&retagged, FileType::Mp4));
+        // The comment header growing renumbers the audio pages
+        let ogg = [ogg_page(0, 0, 1, b"header"), ogg_page(1000, 1, 2, FRAMES)].concat();
+        let retagged = [
+            ogg_page(0, 0, 1, b"header"),
+            ogg_page(0, 1, 5, b"comment"),
+            ogg_page(1000, 2, 6, FRAMES),
+        ]
+        .concat();
+        assert_eq!(md5_of(&ogg, FileType::Vorbis), md5_of(&retagged, FileType::Vorbis));
+    }
+
+    #[test]
+    fn audio_md5_detects_changed_audio() {
+        let changed = flipped(FRAMES);
+        assert_ne!(
+            md5_of(&flac_bytes("a", FRAMES), FileType::Flac),
+            md5_of(&flac_bytes("a", &changed), FileType::Flac)
+        );
+        let (mut mpeg, mut mpeg_changed) = (id3v2_bytes(20), id3v2_bytes(20));
+        mpeg.extend(FRAMES);
+        mpeg_changed.extend(&changed);
+        assert_ne!(md5_of(&mpeg, FileType::Mpeg), md5_of(&mpeg_changed, FileType::Mpeg));
+        assert_ne!(
+            md5_of(&atom(b"mdat", FRAMES), FileType::Mp4),
+            md5_of(&atom(b"mdat", &changed), FileType::Mp4)
+        );
+        assert_ne!(
+            md5_of(&ogg_page(1000, 1, 2, FRAMES), FileType::Opus),
+            md5_of(&ogg_page(1000, 1, 2, &changed), FileType::Opus)
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Responses kept in memory, the least recently used ones are dropped first
+const CACHE_MEMORY_ENTRIES: usize = 1000;
+// Until configured responses are kept for a week
+const CACHE_DEFAULT_TTL_HOURS: u64 = 168;
+
+// Two level cache of deezer responses keyed by endpoint and id, recently used responses are kept in memory
+// while every response is stored on disk as <dir>/<endpoint>/<hash>.json, see disk_path
+// responses older than the ttl are fetched again but can still be served when deezer cannot be reached, see get_expired
+#[derive(Debug)]
+pub struct DzrsResponseCache {
+    dir: Option<PathBuf>,
+    disabled: AtomicBool,
+    ttl_secs: AtomicU64,
+    // Most recently used last
+    memory: Mutex<Vec<(String, DzrsCacheEntry)>>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DzrsCacheEntry {
+    // Full key of the response, checked when read from disk as different keys may share a file name
+    #[serde(default)]
+    key: String,
+    // Seconds since the unix epoch
+    stored: u64,
+    value: serde_json::Value,
+}
+
+impl DzrsResponseCache {
+    // Without a dir responses are only kept in memory
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            disabled: AtomicBool::new(false),
+            ttl_secs: AtomicU64::new(CACHE_DEFAULT_TTL_HOURS * 3600),
+            memory: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn configure(&self, enabled: bool, ttl_hours: u32) {
+        self.disabled.store(!enabled, Ordering::Relaxed);
+        self.ttl_secs.store(ttl_hours as u64 * 3600, Ordering::Relaxed);
+    }
+
+    // Response stored within the ttl
+    pub fn get<T: DeserializeOwned>(&self, endpoint: &str, id: &str) -> Option<T> {
+        let entry = self.entry(endpoint, id)?;
+        match now().saturating_sub(entry.stored) < self.ttl_secs.load(Ordering::Relaxed) {
+            true => serde_json::from_value(entry.value).ok(),
+            false => None,
+        }
+    }
+
+    // Response stored regardless of its age, used when fetching it again failed
+    pub fn get_expired<T: DeserializeOwned>(&self, endpoint: &str, id: &str) -> Option<T> {
+        serde_json::from_value(self.entry(endpoint, id)?.value).ok()
+    }
+
+    pub fn insert<T: Serialize>(&self, endpoint: &str, id: &str, value: &T) {
+        if self.disabled.load(Ordering::Relaxed) {
+            return;
+        };
+        let key = key(endpoint, id);
+        let entry = match serde_json::to_value(value) {
+            Ok(value) => DzrsCacheEntry {
+                key: key.clone(),
+                stored: now(),
+                value,
+            },
+            Err(_) => return,
+        };
+        if let Some(path) = self.disk_path(endpoint, id) {
+            // Failing to write only means fetching again next time
+            let _ = std::fs::create_dir_all(path.parent().unwrap_or(&path))
+                .and_then(|_| serde_json::to_vec(&entry).map_err(std::io::Error::other))
+                .and_then(|json| std::fs::write(&path, json));
+        };
+        self.remember(key, entry);
+    }
+
+    // Drops every response, both from memory and from disk
+    pub fn clear(&self) -> Result<(), String> {
+        self.memory.lock().unwrap().clear();
+        match &self.dir {
+            Some(dir) if dir.exists() => std::fs::remove_dir_all(dir).map_err(|err| err.to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    // Looks into memory first, entries read from disk are kept in memory from then on
+    fn entry(&self, endpoint: &str, id: &str) -> Option<DzrsCacheEntry> {
+        if self.disabled.load(Ordering::Relaxed) {
+            return None;
+        };
+        let key = key(endpoint, id);
+        {
+            let mut memory = self.memory.lock().unwrap();
+            if let Some(i) = memory.iter().position(|(k, _)| k == &key) {
+                let item = memory.remove(i);
+                let entry = item.1.clone();
+                memory.push(item);
+                return Some(entry);
+            };
+        }
+        let json = std::fs::read(self.disk_path(endpoint, id)?).ok()?;
+        let entry: DzrsCacheEntry = serde_json::from_slice(&json).ok()?;
+        if entry.key != key {
+            return None;
+        };
+        self.remember(key, entry.clone());
+        Some(entry)
+    }
+
+    fn remember(&self, key: String, entry: DzrsCacheEntry) {
+        let mut memory = self.memory.lock().unwrap();
+        memory.retain(|(k, _)| k != &key);
+        memory.push((key, entry));
+        if memory.len() > CACHE_MEMORY_ENTRIES {
+            let excess = memory.len() - CACHE_MEMORY_ENTRIES;
+            memory.drain(..excess);
+        };
+    }
+
+    // Files are named by a hash of the id, as ids such as search queries differ by characters, or only by case,
+    // which file names cannot tell apart everywhere
+    fn disk_path(&self, endpoint: &str, id: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        Some(dir.join(sanitize(endpoint)).join(format!("{:016x}.json", fnv1a(id))))
+    }
+}
+
+impl Default for DzrsResponseCache {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+fn key(endpoint: &str, id: &str) -> String {
+    format!("{}/{}", endpoint, id)
+}
+
+// Endpoints are fixed names such as "gw_song", only letters, digits, '-' and '_' are kept for directory names
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || c == '-' {
+            true => c,
+            false => '_',
+        })
+        .collect()
+}
+
+// Hash stable across runs and platforms, unlike the hasher of the standard library
+fn fnv1a(value: &str) -> u64 {
+    value.bytes().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(name: &str) -> DzrsResponseCache {
+        let dir = std::env::temp_dir().join(format!("dzrs-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        DzrsResponseCache::new(Some(dir))
+    }
+
+    #[test]
+    fn sanitize_keeps_safe_characters() {
+        assert_eq!(sanitize("gw_song"), "gw_song");
+        assert_eq!(sanitize("album-tracks2"), "album-tracks2");
+        assert_eq!(sanitize("search/album?q"), "search_album_q");
+        assert_eq!(sanitize("../x"), "___x");
+    }
+
+    #[test]
+    fn fnv1a_is_stable() {
+        assert_eq!(fnv1a(""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a("a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn disk_path_tells_similar_ids_apart() {
+        let cache = DzrsResponseCache::new(Some(PathBuf::from("cache")));
+        let path = |endpoint: &str, id: &str| cache.disk_path(endpoint, id).unwrap();
+        assert_ne!(path("search_album", "q=St.+Anger"), path("search_album", "q=St++Anger"));
+        assert_ne!(path("search_album", "q=abc"), path("search_album", "q=ABC"));
+        assert!(path("search_album", "../../x").starts_with("cache/search_album"));
+        assert_eq!(DzrsResponseCache::default().disk_path("track", "1"), None);
+    }
+
+    #[test]
+    fn entries_are_read_back_from_disk() {
+        let cache = temp_cache("read");
+        cache.insert("track", "1", &"first".to_string());
+        let reopened = DzrsResponseCache::new(cache.dir.clone());
+        assert_eq!(reopened.get::<String>("track", "1"), Some("first".to_string()));
+        assert_eq!(reopened.get::<String>("track", "2"), None);
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn entries_with_another_key_are_rejected() {
+        let cache = temp_cache("key");
+        cache.insert("track", "1", &"first".to_string());
+        // Stand in for a hash collision, the file of id 2 holding the response of id 1
+        let (from, to) = (
+            cache.disk_path("track", "1").unwrap(),
+            cache.disk_path("track", "2").unwrap(),
+        );
+        std::fs::copy(from, to).unwrap();
+        let reopened = DzrsResponseCache::new(cache.dir.clone());
+        assert_eq!(reopened.get::<String>("track", "2"), None);
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn expired_entries_are_only_served_on_request() {
+        let cache = DzrsResponseCache::default();
+        cache.insert("track", "1", &1);
+        cache.configure(true, 0);
+        assert_eq!(cache.get::<u32>("track", "1"), None);
+        assert_eq!(cache.get_expired::<u32>("track", "1"), Some(1));
+        cache.configure(false, 1);
+        assert_eq!(cache.get_expired::<u32>("track", "1"), None);
+    }
+}
@@ -0,0 +1,291 @@
+use crate::config::DzrsConfigurationParsed;
+use crate::types::audio::open_audio_file;
+use crate::types::files::DzrsTrackObject;
+use crate::types::tags::{DzrsTrackObjectPicture, DzrsTrackObjectTags};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum DzrsTagChangeKind {
+    Added,
+    Changed,
+    Removed,
+}
+
+// A single field changing between the tags of a file and the tags to save, multi valued fields are joined with the
+// separator, extra tags are named "extraTags.KEY" and pictures "pictures.TYPE"
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DzrsTagChange {
+    pub field: String,
+    pub kind: DzrsTagChangeKind,
+    pub old_value: String,
+    pub new_value: String,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DzrsTrackObjectDiff {
+    pub file_path: String,
+    pub changes: Vec<DzrsTagChange>,
+}
+
+impl DzrsTagChange {
+    // None when both values are the same
+    fn new(field: &str, old_value: String, new_value: String) -> Option<Self> {
+        let kind = match (old_value.is_empty(), new_value.is_empty()) {
+            _ if old_value == new_value => return None,
+            (true, _) => DzrsTagChangeKind::Added,
+            (_, true) => DzrsTagChangeKind::Removed,
+            _ => DzrsTagChangeKind::Changed,
+        };
+        Some(Self {
+            field: field.to_string(),
+            kind,
+            old_value,
+            new_value,
+        })
+    }
+}
+
+impl DzrsTrackObjectTags {
+    // Field level diff from these tags to the given ones, in field order followed by the extra tags
+    // the cover is left out as it depends on the pictures already in the file, see diff_track
+    // with tag_clear_extra_tags saving drops every extra tag, so they are all reported as removed
+    pub fn diff(&self, other: &Self, conf: &DzrsConfigurationParsed) -> Vec<DzrsTagChange> {
+        let sep = conf.tag_separator.as_str();
+        let mut changes: Vec<DzrsTagChange> = tag_fields(self, sep)
+            .into_iter()
+            .zip(tag_fields(other, sep))
+            .filter_map(|((field, old_value), (_, new_value))| DzrsTagChange::new(field, old_value, new_value))
+            .collect();
+        let old_extra = extra_tag_values(&self.extra_tags, sep);
+        let new_extra = match conf.tag_clear_extra_tags {
+            true => Vec::new(),
+            false => extra_tag_values(&other.extra_tags, sep),
+        };
+        let mut keys: Vec<&String> = old_extra.iter().map(|(k, _)| k).collect();
+        keys.extend(
+            new_extra
+                .iter()
+                .map(|(k, _)| k)
+                .filter(|k| !old_extra.iter().any(|(k1, _)| k1 == *k)),
+        );
+        for key in keys {
+            let value = |values: &[(String, String)]| {
+                values
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, v)| v.clone())
+                    .unwrap_or_default()
+            };
+            let field = format!("extraTags.{}", key);
+            if let Some(change) = DzrsTagChange::new(&field, value(&old_extra), value(&new_extra)) {
+                changes.push(change);
+            };
+        }
+        changes
+    }
+}
+
+// Diff between the tags of a file and the tags which saving would write, including the cover embedded following
+// tag_cover_policy, pictures not loaded yet are read from the file only when a cover is pending
+pub fn diff_track(
+    track: &DzrsTrackObject,
+    tags: &DzrsTrackObjectTags,
+    conf: &DzrsConfigurationParsed,
+) -> Result<DzrsTrackObjectDiff, String> {
+    let mut changes = track.tags.diff(tags, conf);
+    if let Some(cover) = &tags.cover {
+        let pictures = match track.pictures_loaded {
+            true => track.tags_pictures.clone(),
+            false => open_audio_file(&track.file_path)?.read_pictures(),
+        };
+        if let Some(change) = diff_cover(&pictures, cover, &conf.tag_cover_policy) {
+            changes.push(change);
+        };
+    };
+    Ok(DzrsTrackObjectDiff {
+        file_path: track.file_path.clone(),
+        changes,
+    })
+}
+
+// Pictures are described by their size, e.g. "1200x1200"
+fn diff_cover(
+    pictures: &[DzrsTrackObjectPicture],
+    cover: &DzrsTrackObjectPicture,
+    policy: &str,
+) -> Option<DzrsTagChange> {
+    let field = format!("pictures.{}", cover.pic_type);
+    let describe = |p: &DzrsTrackObjectPicture| format!("{}x{}", p.width, p.height);
+    let front_cover = pictures.iter().find(|p| p.pic_type == cover.pic_type);
+    match (front_cover, policy) {
+        (Some(_), "keep") => None,
+        (Some(p), "replace") if p.b64 == cover.b64 => None,
+        (Some(p), "replace") => DzrsTagChange::new(&field, describe(p), describe(cover)),
+        _ => DzrsTagChange::new(&field, String::new(), describe(cover)),
+    }
+}
+
+// Values of the editable fields named as in the front-end, length is left out as it is read from the audio
+fn tag_fields(tags: &DzrsTrackObjectTags, sep: &str) -> Vec<(&'static str, String)> {
+    vec![
+        ("title", tags.title.clone()),
+        ("artist", tags.artist.join(sep)),
+        ("album", tags.album.clone()),
+        ("albumArtist", tags.album_artist.clone()),
+        ("composer", tags.composer.join(sep)),
+        ("performer", tags.performer.join(sep)),
+        ("producer", tags.producer.join(sep)),
+        ("genre", tags.genre.join(sep)),
+        ("lyrics", tags.lyrics.clone()),
+        ("copyright", tags.copyright.clone()),
+        ("description", tags.description.clone()),
+        ("trackNumber", tags.track_number.clone()),
+        ("trackTotal", tags.track_total.clone()),
+        ("diskNumber", tags.disk_number.clone()),
+        ("diskTotal", tags.disk_total.clone()),
+        ("date", tags.date.clone()),
+        ("year", tags.year.clone()),
+        ("originalDate", tags.original_date.clone()),
+        ("comment", tags.comment.clone()),
+        ("label", tags.label.join(sep)),
+        ("organization", tags.organization.join(sep)),
+        ("barcode", tags.barcode.clone()),
+        ("isrc", tags.isrc.clone()),
+        ("bpm", tags.bpm.clone()),
+        ("explicit", tags.explicit.clone()),
+        ("replaygainAlbumGain", tags.replaygain_album_gain.clone()),
+        ("replaygainAlbumPeak", tags.replaygain_album_peak.clone()),
+        ("replaygainTrackGain", tags.replaygain_track_gain.clone()),
+        ("replaygainTrackPeak", tags.replaygain_track_peak.clone()),
+        ("sourceId", tags.source_id.clone()),
+        ("encoder", tags.encoder.clone()),
+    ]
+}
+
+// Extra tags may repeat a key, their values are joined in order of appearance
+fn extra_tag_values(extra_tags: &[(String, String)], sep: &str) -> Vec<(String, String)> {
+    let mut values: Vec<(String, Vec<String>)> = Vec::new();
+    for (key, value) in extra_tags {
+        match values.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => v.push(value.clone()),
+            None => values.push((key.clone(), vec![value.clone()])),
+        };
+    }
+    values.into_iter().map(|(k, v)| (k, v.join(sep))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conf(clear_extra_tags: bool) -> DzrsConfigurationParsed {
+        DzrsConfigurationParsed {
+            tag_separator: "; ".to_string(),
+            tag_clear_extra_tags: clear_extra_tags,
+            ..Default::default()
+        }
+    }
+
+    fn picture(b64: &str, size: u32) -> DzrsTrackObjectPicture {
+        DzrsTrackObjectPicture {
+            b64: b64.to_string(),
+            pic_type: "CoverFront".to_string(),
+            width: size,
+            height: size,
+            ..Default::default()
+        }
+    }
+
+    fn extra(tags: &[(&str, &str)]) -> DzrsTrackObjectTags {
+        DzrsTrackObjectTags {
+            extra_tags: tags.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tag_change_kinds() {
+        let change = |old: &str, new: &str| DzrsTagChange::new("title", old.to_string(), new.to_string());
+        assert_eq!(change("", ""), None);
+        assert_eq!(change("a", "a"), None);
+        assert_eq!(change("", "a").unwrap().kind, DzrsTagChangeKind::Added);
+        assert_eq!(change("a", "").unwrap().kind, DzrsTagChangeKind::Removed);
+        assert_eq!(change("a", "b").unwrap().kind, DzrsTagChangeKind::Changed);
+    }
+
+    #[test]
+    fn diff_joins_multi_valued_fields() {
+        let old = DzrsTrackObjectTags {
+            artist: vec!["A".to_string()],
+            ..Default::default()
+        };
+        let new = DzrsTrackObjectTags {
+            artist: vec!["A".to_string(), "B".to_string()],
+            ..Default::default()
+        };
+        let changes = old.diff(&new, &conf(false));
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, "artist");
+        assert_eq!(changes[0].new_value, "A; B");
+    }
+
+    #[test]
+    fn diff_extra_tags() {
+        let old = extra(&[("MOOD", "calm"), ("KEY", "C"), ("KEY", "D")]);
+        let new = extra(&[("MOOD", "happy"), ("KEY", "C"), ("KEY", "D"), ("LANGUAGE", "eng")]);
+        let changes = old.diff(&new, &conf(false));
+        let fields: Vec<(&str, &DzrsTagChangeKind)> = changes.iter().map(|c| (c.field.as_str(), &c.kind)).collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("extraTags.MOOD", &DzrsTagChangeKind::Changed),
+                ("extraTags.LANGUAGE", &DzrsTagChangeKind::Added)
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_clear_extra_tags_removes_all() {
+        let old = extra(&[("MOOD", "calm"), ("KEY", "C")]);
+        let new = extra(&[("MOOD", "calm"), ("LANGUAGE", "eng")]);
+        let changes = old.diff(&new, &conf(true));
+        assert_eq!(changes.len(), 2);
+        assert!(changes.iter().all(|c| c.kind == DzrsTagChangeKind::Removed));
+        assert_eq!(changes[0].field, "extraTags.MOOD");
+        assert_eq!(changes[1].field, "extraTags.KEY");
+    }
+
+    #[test]
+    fn diff_cover_keep_policy() {
+        let cover = picture("new", 1000);
+        assert_eq!(diff_cover(&[picture("old", 500)], &cover, "keep"), None);
+        let change = diff_cover(&[], &cover, "keep").unwrap();
+        assert_eq!(change.field, "pictures.CoverFront");
+        assert_eq!(change.kind, DzrsTagChangeKind::Added);
+        assert_eq!(change.new_value, "1000x1000");
+    }
+
+    #[test]
+    fn diff_cover_replace_policy() {
+        let cover = picture("new", 1000);
+        assert_eq!(diff_cover(&[picture("new", 1000)], &cover, "replace"), None);
+        let change = diff_cover(&[picture("old", 500)], &cover, "replace").unwrap();
+        assert_eq!(change.kind, DzrsTagChangeKind::Changed);
+        assert_eq!(
+            (change.old_value.as_str(), change.new_value.as_str()),
+            ("500x500", "1000x1000")
+        );
+    }
+
+    #[test]
+    fn diff_cover_other_picture_types_do_not_count() {
+        let mut back = picture("old", 500);
+        back.pic_type = "CoverBack".to_string();
+        let change = diff_cover(&[back], &picture("new", 1000), "replace").unwrap();
+        assert_eq!(change.kind, DzrsTagChangeKind::Added);
+    }
+}
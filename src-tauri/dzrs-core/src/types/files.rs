@@ -0,0 +1,969 @@
+use crate::config::DzrsConfigurationParsed;
+use crate::types::audio::{
+    encode_jpeg, open_audio_file, picture_type_from_str, DzrsTrackObjectProperties, TEMP_FILE_SUFFIX,
+};
+use crate::types::matching::{clean_album, clean_title};
+use crate::types::tags::{DeezerTagger, DzrsTrackObjectPicture, DzrsTrackObjectTagSource, DzrsTrackObjectTags};
+use crate::types::template::{parse_file_path, render_file_name, render_path};
+
+use lofty::{MimeType, Picture, PictureType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{File, FileTimes, OpenOptions};
+use std::io::BufReader;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+
+// Files with the same name as the track, moved along with it
+const SIDECAR_EXTENSIONS: [&str; 2] = ["lrc", "cue"];
+// Files shared by every track within a directory, copied along with a track
+const SHARED_SIDECARS: [&str; 2] = ["cover.jpg", "folder.jpg"];
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DzrsTrackObjectWrapper {
+    pub items: Vec<DzrsTrackObject>,
+    // Scanned directory, relative paths of the inner DzrsTrackObjects are based on it
+    pub root: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DzrsTrackObject {
+    pub file_path: String,
+    pub file_name: String,
+    pub relative_path: String,
+    pub file_size: u64,
+    // Milliseconds since the unix epoch, used along with file_size for detecting changed files
+    pub file_modified: u64,
+    pub file_extension: String,
+    pub file_type: String,
+    pub audio_properties: DzrsTrackObjectProperties,
+    pub tags_status: DzrsTrackObjectTagState,
+    pub tags: DzrsTrackObjectTags,
+    pub tags_deezer: DzrsTrackObjectTags,
+    pub tags_to_save: DzrsTrackObjectTags,
+    pub tags_sources: Vec<DzrsTrackObjectTagSource>,
+    // Pictures are only read on demand, see tracks_pictures
+    pub tags_pictures: Vec<DzrsTrackObjectPicture>,
+    pub pictures_loaded: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum DzrsTrackObjectTagState {
+    #[default]
+    NotFetched,
+    Unsuccessfull,
+    Successfull, // When the applied match scored below tag_match_review, user has to ensure it actually matches the file
+    Matched,
+    Finalized,
+}
+
+// Save given DzrsTrackObjectTags into a file, by manipulating the tags stored in the file
+pub fn save_tags<P: AsRef<Path>>(
+    path: P,
+    tags: &DzrsTrackObjectTags,
+    conf: &DzrsConfigurationParsed,
+) -> Result<(), String> {
+    let path = path.as_ref();
+    let mut file = open_audio_file(path)?;
+    file.write_tags(tags, conf)?;
+    file.write_cover(tags, conf)?;
+    if conf.tag_cover_resize {
+        file.resize_pictures(conf.tag_cover_max_size, conf.tag_cover_quality)?;
+    };
+    file.save_safely(path, conf.tag_keep_mtime)
+}
+
+// Saves tags into a file and reloads it, with directory_move_on_save the file is then moved into the output directory
+// Returns the reloaded DzrsTrackObject, pointing to the moved file if any, along with the errors of moving the file
+// the tags are saved either way
+pub fn save_track(
+    path: &str,
+    tags: &DzrsTrackObjectTags,
+    conf: &DzrsConfigurationParsed,
+) -> Result<(DzrsTrackObject, Vec<String>), String> {
+    let mut errors: Vec<String> = Vec::new();
+    save_tags(path, tags, conf)?;
+    let mut track = DzrsTrackObject::new(path)?;
+    track.load_tags(conf)?;
+    if conf.directory_move_on_save && !conf.directory_output.is_empty() {
+        // Without a directory template files are moved flat into the output directory
+        let relative_path = match conf.directory_template.is_empty() {
+            true => Ok(PathBuf::from(&track.file_name)),
+            false => render_path(
+                &conf.directory_template,
+                &track.tags,
+                &conf.tag_separator,
+                &track.file_extension,
+            ),
+        };
+        match relative_path {
+            Ok(relative_path) => {
+                let new_path = PathBuf::from(&conf.directory_output).join(relative_path);
+                match move_track(&track.file_path, &new_path) {
+                    Ok(errs) => {
+                        errors.extend(errs);
+                        track.file_path = new_path.to_str().unwrap_or_default().to_string();
+                        track.file_name = new_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                    }
+                    Err(err) => errors.push(err),
+                };
+            }
+            Err(err) => errors.push(err),
+        };
+    }
+    Ok((track, errors))
+}
+
+// Exports the embedded FrontCover, or the first picture when missing, as a jpeg named file_name next to the file
+pub fn export_picture<P: AsRef<Path>>(path: P, file_name: &str, conf: &DzrsConfigurationParsed) -> Result<(), String> {
+    let path = path.as_ref();
+    if !["cover.jpg", "folder.jpg"].contains(&file_name) {
+        return Err(format!("Invalid cover file name {}", file_name));
+    };
+    let pictures = open_audio_file(path)?.pictures();
+    let picture = match pictures.iter().find(|p| p.pic_type() == PictureType::CoverFront) {
+        Some(p) => p,
+        None => pictures
+            .first()
+            .ok_or_else(|| format!("No pictures found in {}", path.display()))?,
+    };
+    let data = match picture.mime_type() {
+        MimeType::Jpeg => picture.data().to_vec(),
+        _ => {
+            let img = image::load_from_memory(picture.data()).map_err(|err| err.to_string())?;
+            encode_jpeg(&img, conf.tag_cover_quality)?
+        }
+    };
+    let out = path.with_file_name(file_name);
+    std::fs::write(out, data).map_err(|err| err.to_string())
+}
+
+// Embeds an image from disk as the given picture type, replacing existing pictures of that type
+pub fn import_picture<P: AsRef<Path>>(
+    path: P,
+    image_path: &str,
+    pic_type: &str,
+    conf: &DzrsConfigurationParsed,
+) -> Result<(), String> {
+    let path = path.as_ref();
+    let pic_type = picture_type_from_str(pic_type)?;
+    let image = File::open(image_path).map_err(|err| err.to_string())?;
+    let mut picture = Picture::from_reader(&mut BufReader::new(image)).map_err(|err| err.to_string())?;
+    picture.set_pic_type(pic_type);
+    let mut file = open_audio_file(path)?;
+    file.insert_picture(picture)?;
+    file.save_safely(path, conf.tag_keep_mtime)
+}
+
+// Removes every embedded picture of the given type
+pub fn remove_pictures<P: AsRef<Path>>(path: P, pic_type: &str, conf: &DzrsConfigurationParsed) -> Result<(), String> {
+    let path = path.as_ref();
+    let pic_type = picture_type_from_str(pic_type)?;
+    let mut file = open_audio_file(path)?;
+    file.remove_picture_type(pic_type)?;
+    file.save_safely(path, conf.tag_keep_mtime)
+}
+
+// Moves a file creating any missing parent directory, existing files are never overwritten
+// rename fails across filesystems, in which case the file is copied, keeping its times and permissions, and then removed
+pub fn move_file<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<(), String> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    if to.exists() {
+        return Err(format!(
+            "Cannot move {} to {}, file already exists",
+            from.display(),
+            to.display()
+        ));
+    };
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+    };
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    };
+    if let Err(err) = copy_file(from, to) {
+        let _ = std::fs::remove_file(to);
+        return Err(err.to_string());
+    };
+    std::fs::remove_file(from).map_err(|err| err.to_string())
+}
+
+// Times are set before the permissions, as a read only file could not be opened for it afterwards
+fn copy_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    let metadata = std::fs::metadata(from)?;
+    std::fs::copy(from, to)?;
+    let times = FileTimes::new()
+        .set_accessed(metadata.accessed()?)
+        .set_modified(metadata.modified()?);
+    OpenOptions::new().write(true).open(to)?.set_times(times)?;
+    std::fs::set_permissions(to, metadata.permissions())
+}
+
+// Moves a track to a new path along with its sidecar files, .lrc and .cue files are renamed to match the track
+// while cover.jpg and folder.jpg are copied since other tracks in the directory may still need them
+// Errors for sidecars are collected and returned once the track itself has been moved
+pub fn move_track<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<Vec<String>, String> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    move_file(from, to)?;
+    let mut errors = Vec::new();
+    let (from_dir, to_dir) = match (from.parent(), to.parent()) {
+        (Some(from_dir), Some(to_dir)) => (from_dir, to_dir),
+        _ => return Ok(errors),
+    };
+    for ext in SIDECAR_EXTENSIONS {
+        let sidecar = from.with_extension(ext);
+        if sidecar.is_file() {
+            if let Err(err) = move_file(&sidecar, to.with_extension(ext)) {
+                errors.push(err);
+            };
+        };
+    }
+    if from_dir != to_dir {
+        for name in SHARED_SIDECARS {
+            let (sidecar, target): (PathBuf, PathBuf) = (from_dir.join(name), to_dir.join(name));
+            if sidecar.is_file() && !target.exists() {
+                if let Err(err) = std::fs::copy(&sidecar, &target) {
+                    errors.push(err.to_string());
+                };
+            };
+        }
+    };
+    Ok(errors)
+}
+
+// Collects files within a directory, descending into subdirectories up to max_depth (0 only reads dir itself)
+// symlinks are skipped unless directory_follow_symlinks, visited keeps symlinked directories from looping
+fn scan_dir(
+    dir: &Path,
+    depth: u32,
+    max_depth: u32,
+    conf: &DzrsConfigurationParsed,
+    visited: &mut HashSet<PathBuf>,
+    paths: &mut Vec<PathBuf>,
+) {
+    if let Ok(canonical) = dir.canonicalize() {
+        if !visited.insert(canonical) {
+            return;
+        };
+    };
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if is_ignored(&path, conf) {
+            continue;
+        };
+        let file_type = match entry.file_type() {
+            Ok(ft) if ft.is_symlink() && !conf.directory_follow_symlinks => continue,
+            Ok(ft) if ft.is_symlink() => match std::fs::metadata(&path) {
+                Ok(metadata) => metadata.file_type(),
+                Err(_) => continue,
+            },
+            Ok(ft) => ft,
+            Err(_) => continue,
+        };
+        if file_type.is_dir() {
+            if depth < max_depth {
+                scan_dir(&path, depth + 1, max_depth, conf, visited, paths);
+            };
+        } else {
+            paths.push(path);
+        }
+    }
+}
+
+// Whether a file or directory name matches one of the ';' separated directory_ignore globs
+pub fn is_ignored(path: &Path, conf: &DzrsConfigurationParsed) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    // Leftovers of an interrupted save
+    if name.ends_with(TEMP_FILE_SUFFIX) {
+        return true;
+    };
+    conf.directory_ignore
+        .split(';')
+        .map(|g| g.trim())
+        .filter(|g| !g.is_empty())
+        .any(|g| glob_match(g, &name))
+}
+
+// Minimal glob matching supporting '*' for any sequence and '?' for a single character
+fn glob_match(glob: &str, name: &str) -> bool {
+    let (glob, name): (Vec<char>, Vec<char>) = (glob.chars().collect(), name.chars().collect());
+    let (mut g, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if g < glob.len() && (glob[g] == '?' || glob[g] == name[n]) {
+            g += 1;
+            n += 1;
+        } else if g < glob.len() && glob[g] == '*' {
+            star = Some((g, n));
+            g += 1;
+        } else if let Some((sg, sn)) = star {
+            g = sg + 1;
+            n = sn + 1;
+            star = Some((sg, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    glob[g..].iter().all(|c| *c == '*')
+}
+
+// Whether both paths lead to the same file, compared by device and inode where available
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (std::fs::metadata(a), std::fs::metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        }
+    }
+    // Canonical paths carry the case stored on disk on windows
+    #[cfg(not(unix))]
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// Path of a file relative to the scanned directory, used for display
+fn relative_path(root: &str, path: &Path) -> String {
+    match path.strip_prefix(root) {
+        Ok(relative) => relative.to_str().unwrap_or_default().to_string(),
+        Err(_) => path
+            .file_name()
+            .unwrap_or_default()
+            .to_str()
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+impl DzrsTrackObjectWrapper {
+    // Create a new DzrsTrackObjectWrapper which contains all files within a given directory
+    // subdirectories are scanned as well with directory_recursive, see scan_dir
+    pub fn new<P: AsRef<Path>>(dir: P, conf: &DzrsConfigurationParsed) -> Result<Self, String> {
+        let dir: &Path = dir.as_ref();
+        if dir.exists() && dir.is_dir() {
+            let mut paths: Vec<PathBuf> = vec![];
+            let mut visited: HashSet<PathBuf> = HashSet::new();
+            let max_depth = match conf.directory_recursive {
+                true => conf.directory_max_depth,
+                false => 0,
+            };
+            scan_dir(dir, 0, max_depth, conf, &mut visited, &mut paths);
+            let root = dir.to_str().unwrap_or_default().to_string();
+            let mut items: Vec<DzrsTrackObject> = vec![];
+            for path in paths {
+                if let Ok(mut tr) = DzrsTrackObject::new(&path) {
+                    tr.relative_path = relative_path(&root, &path);
+                    items.push(tr);
+                };
+            }
+            Ok(Self { items, root })
+        } else {
+            Err(format!("Invalid path {}", dir.to_str().unwrap()))
+        }
+    }
+
+    // Clears all inner DzrsTrackObjects
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.root.clear();
+    }
+
+    // Creates a new DzrsTrackObject from a given path and adds it
+    pub fn add_track<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
+        let path = path.as_ref().to_str().unwrap();
+        match self.get_track_obj(path) {
+            Some(_) => Err("Cannot add duplicate file".to_string()),
+            None => {
+                let tr = DzrsTrackObject::new(path);
+                match tr {
+                    Ok(mut tr) => {
+                        tr.relative_path = relative_path(&self.root, Path::new(path));
+                        self.items.push(tr);
+                        Ok(())
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+        }
+    }
+
+    // Replace an existing inner DzrsTrackObject based on its path by reloading the file
+    pub fn replace_track<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
+        let path = path.as_ref().to_str().unwrap();
+        match self.get_track_obj_mut(path) {
+            Some(tr1) => {
+                let tr2 = DzrsTrackObject::new(path);
+                match tr2 {
+                    Ok(mut tr2) => {
+                        tr2.relative_path = relative_path(&self.root, Path::new(path));
+                        *tr1 = tr2;
+                        Ok(())
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+            None => Err(format!("Cannot replace for {}", path)),
+        }
+    }
+
+    // Replace an existing inner DzrsTrackObject based on its path by swapping it with a given one
+    pub fn replace_track_obj(&mut self, tr: DzrsTrackObject) -> Result<(), String> {
+        match self.iter().position(|i| i.file_path == tr.file_path) {
+            Some(i) => {
+                self[i] = tr;
+                Ok(())
+            }
+            None => Err(format!("Cannot insert for {}", &tr.file_path)),
+        }
+    }
+
+    // Replace an existing inner DzrsTrackObject based on its path OR add a new one by reloading the file
+    pub fn insert_track<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
+        let path = path.as_ref().to_str().unwrap();
+        let mut tr1: DzrsTrackObject = match DzrsTrackObject::new(path) {
+            Ok(tr1) => tr1,
+            Err(err) => return Err(err),
+        };
+        tr1.relative_path = relative_path(&self.root, Path::new(path));
+        match self.get_track_obj_mut(path) {
+            Some(tr2) => {
+                *tr2 = tr1;
+            }
+            None => {
+                self.items.push(tr1);
+            }
+        }
+        Ok(())
+    }
+
+    // Remove an existing inner DzrsTrackObject based on its path
+    pub fn remove_track<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
+        let path = path.as_ref().to_str().unwrap();
+        let i = self.iter().position(|i| i.file_path == path);
+        match i {
+            Some(i) => {
+                self.items.remove(i);
+                Ok(())
+            }
+            None => Err(format!("Cannot remove for {}", path)),
+        }
+    }
+
+    // Renames the file of an inner DzrsTrackObject, the new path must not be taken by another file
+    pub fn rename_track<P: AsRef<Path>>(&mut self, path: &str, new_path: P) -> Result<(), String> {
+        let new_path = new_path.as_ref();
+        let new_path_str = new_path.to_str().unwrap_or_default();
+        // Case only renames point to the same file on case insensitive filesystems, any other existing file collides
+        let same_file = new_path.exists() && is_same_file(Path::new(path), new_path);
+        if !same_file && (self.get_track_obj(new_path_str).is_some() || new_path.exists()) {
+            return Err(format!(
+                "Cannot rename {} to {}, file already exists",
+                path,
+                new_path.display()
+            ));
+        };
+        let tr = match self.get_track_obj_mut(path) {
+            Some(tr) => tr,
+            None => return Err(format!("Cannot rename for {}", path)),
+        };
+        std::fs::rename(path, new_path).map_err(|err| err.to_string())?;
+        tr.relative_path = relative_path(&self.root, new_path);
+        tr.file_path = new_path_str.to_string();
+        tr.file_name = new_path
+            .file_name()
+            .unwrap_or_default()
+            .to_str()
+            .unwrap_or_default()
+            .to_string();
+        Ok(())
+    }
+
+    // Renames the file of an inner DzrsTrackObject from its current tags using the file_template, the extension is kept
+    // unsupported files have no tags to render the template with and are left alone, like files already matching it
+    pub fn rename_from_template(&mut self, path: &str, conf: &DzrsConfigurationParsed) -> Result<(), String> {
+        let new_path = match self.get_track_obj(path) {
+            Some(tr) if !tr.file_type.is_empty() => {
+                let file_name =
+                    render_file_name(&conf.file_template, &tr.tags, &conf.tag_separator, &tr.file_extension)?;
+                Path::new(path).with_file_name(file_name)
+            }
+            Some(_) => return Ok(()),
+            None => return Err(format!("Cannot rename for {}", path)),
+        };
+        if new_path == Path::new(path) {
+            return Ok(());
+        };
+        self.rename_track(path, &new_path)
+    }
+
+    // Updates an inner DzrsTrackObject to match its file once saved, see save_track
+    // a file moved outside of root no longer belongs to the scanned directory and is dropped
+    pub fn apply_saved(&mut self, path: &str, saved: DzrsTrackObject) -> Result<(), String> {
+        let i = match self.iter().position(|i| i.file_path == path) {
+            Some(i) => i,
+            None => return Err(format!("Cannot find inner DzrsTrackObject for {}", path)),
+        };
+        if saved.file_path != path {
+            let new_path = Path::new(&saved.file_path);
+            if new_path.strip_prefix(&self.root).is_err() {
+                self.items.remove(i);
+                return Ok(());
+            };
+            let relative_path = relative_path(&self.root, new_path);
+            let tr = &mut self.items[i];
+            tr.file_path = saved.file_path.clone();
+            tr.file_name = saved.file_name.clone();
+            tr.relative_path = relative_path;
+        };
+        let tr = &mut self.items[i];
+        tr.file_size = saved.file_size;
+        tr.file_modified = saved.file_modified;
+        tr.tags = saved.tags;
+        tr.tags_pictures = saved.tags_pictures;
+        tr.pictures_loaded = saved.pictures_loaded;
+        tr.tags_to_save = saved.tags_to_save;
+        tr.tags_status = DzrsTrackObjectTagState::Finalized;
+        Ok(())
+    }
+
+    // Loads the tags of the inner DzrsTrackObjects not loaded yet (e.g. not served from the library index)
+    // split across as many threads as available, errors are ignored for unsupported files
+    pub fn load_tags(&mut self, conf: &DzrsConfigurationParsed) {
+        let workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+        let mut pending: Vec<&mut DzrsTrackObject> = self.iter_mut().filter(|tr| tr.file_type.is_empty()).collect();
+        let chunk_size = pending.len().div_ceil(workers).max(1);
+        std::thread::scope(|s| {
+            for chunk in pending.chunks_mut(chunk_size) {
+                s.spawn(move || {
+                    chunk.iter_mut().for_each(|track| {
+                        let _ = track.load_tags(conf);
+                    })
+                });
+            }
+        });
+    }
+
+    // Whether a path within root falls under the directory_ignore globs, only the part relative to root is matched
+    // so an ignored folder above root does not hide the whole directory
+    pub fn is_ignored(&self, path: &str, conf: &DzrsConfigurationParsed) -> bool {
+        let relative = Path::new(path).strip_prefix(&self.root).unwrap_or(Path::new(path));
+        relative.ancestors().any(|p| is_ignored(p, conf))
+    }
+
+    pub fn get_track_obj(&self, path: &str) -> Option<&DzrsTrackObject> {
+        self.iter().find(|&i| &i.file_path == path)
+    }
+
+    pub fn get_track_obj_mut(&mut self, path: &str) -> Option<&mut DzrsTrackObject> {
+        self.iter_mut().find(|i| &i.file_path == path)
+    }
+}
+
+// Modification time of a file in milliseconds since the unix epoch, 0 when not available
+pub fn modified_millis(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+impl DzrsTrackObject {
+    // Load a file and its information into a DzrsTrackObject from a given path
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path: &Path = path.as_ref();
+        if !path.is_file() {
+            return Err(format!("Not a file {}", path.to_str().unwrap()));
+        }
+        // Initialize an empty DzrsTrackObject
+        let mut track_obj = Self::default();
+        // Load basic file information
+        track_obj.file_path = path.to_str().unwrap_or_default().to_string();
+        if let Ok(metadata) = std::fs::metadata(path) {
+            track_obj.file_name = path
+                .file_name()
+                .unwrap_or_default()
+                .to_str()
+                .unwrap_or_default()
+                .to_string();
+            track_obj.file_extension = path
+                .extension()
+                .unwrap_or_default()
+                .to_str()
+                .unwrap_or_default()
+                .to_string();
+            track_obj.file_size = metadata.len();
+            track_obj.file_modified = modified_millis(&metadata);
+        }
+        Ok(track_obj)
+    }
+
+    // Tags used for building deezer queries, with tag_filename_before_fetch untagged files first get their
+    // tags_to_save parsed from the file name, which makes a much better query than the raw file name
+    pub fn query_tags(&mut self, conf: &DzrsConfigurationParsed) -> DzrsTrackObjectTags {
+        if conf.tag_filename_before_fetch && self.tags.title.is_empty() {
+            let path = PathBuf::from(&self.file_path);
+            if parse_file_path(
+                &conf.tag_filename_pattern,
+                &path,
+                &mut self.tags_to_save,
+                &conf.tag_separator,
+            )
+            .is_ok()
+            {
+                return self.tags_to_save.clone();
+            };
+        };
+        self.tags.clone()
+    }
+
+    // Fills tags_to_save by parsing the file path with the tag_filename_pattern
+    pub fn tags_from_filename(&mut self, conf: &DzrsConfigurationParsed) -> Result<(), String> {
+        let path = PathBuf::from(&self.file_path);
+        parse_file_path(
+            &conf.tag_filename_pattern,
+            &path,
+            &mut self.tags_to_save,
+            &conf.tag_separator,
+        )
+    }
+
+    // Tags the sources are scored against, untagged files are compared by their file name
+    pub fn match_tags(&self, tags: &DzrsTrackObjectTags) -> DzrsTrackObjectTags {
+        let mut tags = tags.clone();
+        if tags.title.is_empty() {
+            tags.title = Path::new(&self.file_name)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+        };
+        tags
+    }
+
+    // Fetch possibile tracks matching the track metadata (sources) from deezer into tags_sources
+    pub async fn fetch_sources(&mut self, tagger: &DeezerTagger, conf: &DzrsConfigurationParsed) -> Result<(), String> {
+        // Stripping '&' messes up the url
+        let query_tags = self.query_tags(conf);
+        let _title = query_tags.title.replace("&", "");
+        let _album = query_tags.album.replace("&", "");
+        let _artist = query_tags.artist.join(" ").replace("&", "");
+        let tr_meta = (_title.deref(), _album.deref(), _artist.deref());
+        let query = match (
+            tr_meta.0.is_empty() && tr_meta.1.is_empty() && tr_meta.2.is_empty(),
+            conf.tag_fetch_with_filename,
+        ) {
+            (true, true) => Path::new(&self.file_name)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into(),
+            _ => format!(r#"track:"{}" album:"{}" artist:"{}""#, tr_meta.0, tr_meta.1, tr_meta.2),
+        };
+        let match_tags = self.match_tags(&query_tags);
+        self.tags_sources = tagger
+            .fetch_sources(&query, &match_tags, self.audio_properties.duration)
+            .await?;
+        Ok(())
+    }
+
+    // Fetch tags for a specific deezer track id and apply them into tags_deezer and tags_to_save
+    // used when applying a different deezer source over the file
+    pub async fn fetch_source(&mut self, tagger: &DeezerTagger, id: u64, conf: &DzrsConfigurationParsed) {
+        let payload = tagger
+            .fetch_by_id(id, conf.tag_dz_cover.then_some(conf.tag_cover_size))
+            .await;
+        self.tags_deezer.apply_deezer(payload.clone(), conf);
+        self.tags_to_save.apply_deezer(payload, conf);
+        self.tags_status = DzrsTrackObjectTagState::Matched;
+    }
+
+    // Fetch tags from deezer using the track metadata and apply them into tags_deezer and tags_to_save
+    // every source found is scored against the file and the best one is applied when scoring at least
+    // tag_match_threshold, below tag_match_review it is left Successfull for review
+    // with tag_fetch_exact files already identified by their tags are looked up exactly first, see DeezerTagger::fetch_exact
+    // the tags_status is updated either way, a failed search leaves it Unsuccessfull
+    pub async fn fetch_tags(&mut self, tagger: &DeezerTagger, conf: &DzrsConfigurationParsed) -> Result<(), String> {
+        if conf.tag_fetch_exact {
+            if let Some(id) = tagger.fetch_exact(&self.tags).await {
+                self.fetch_source(tagger, id, conf).await;
+                return Ok(());
+            };
+        };
+        // Stripping featured artists from the title and explicit from album, messes up the deezer search
+        // Also stripping '&' messes up the url
+        let query_tags = self.query_tags(conf);
+        let _title = query_tags.title.replace("&", "");
+        let _album = query_tags.album.replace("&", "");
+        let _artist = query_tags.artist.join(" ").replace("&", "");
+        let _title = clean_title(&_title);
+        let _album = clean_album(&_album);
+        let file_name = Path::new(&self.file_name)
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let tr_meta = (_title.deref(), _album.deref(), _artist.deref());
+
+        let queries = match (
+            tr_meta.0.is_empty() && tr_meta.1.is_empty() && tr_meta.2.is_empty(),
+            conf.tag_fetch_with_filename,
+        ) {
+            (true, true) => (file_name.clone(), file_name),
+            _ => (
+                format!(r#"track:"{}" album:"{}" artist:"{}""#, tr_meta.0, tr_meta.1, tr_meta.2),
+                format!(r#"track:"{}" album:"{}""#, tr_meta.0, tr_meta.1),
+            ),
+        };
+        let match_tags = self.match_tags(&query_tags);
+        let duration = self.audio_properties.duration;
+        let mut sources = tagger.fetch_sources(&queries.0, &match_tags, duration).await;
+        // The looser query is only tried when the first one finds nothing good enough, its sources are merged
+        let best_score = |sources: &Result<Vec<DzrsTrackObjectTagSource>, String>| match sources {
+            Ok(sources) => sources.first().map(|s| s.score).unwrap_or_default(),
+            Err(_) => 0,
+        };
+        if best_score(&sources) < conf.tag_match_threshold && queries.0 != queries.1 {
+            if let Ok(more) = tagger.fetch_sources(&queries.1, &match_tags, duration).await {
+                let mut merged = sources.unwrap_or_default();
+                for source in more {
+                    if !merged.iter().any(|s| s.id == source.id) {
+                        merged.push(source);
+                    };
+                }
+                merged.sort_by(|a, b| b.score.cmp(&a.score));
+                sources = Ok(merged);
+            };
+        };
+        let sources = match sources {
+            Ok(sources) => sources,
+            Err(err) => {
+                self.tags_status = DzrsTrackObjectTagState::Unsuccessfull;
+                return Err(err);
+            }
+        };
+        let best = sources.first().map(|s| (s.id, s.score));
+        self.tags_sources = sources;
+
+        // Update the DzrsTrackObject using the tags of the best source
+        match best {
+            Some((id, score)) if score >= conf.tag_match_threshold => {
+                let payload = tagger
+                    .fetch_by_id(id, conf.tag_dz_cover.then_some(conf.tag_cover_size))
+                    .await;
+                self.tags_deezer.apply_deezer(payload.clone(), conf);
+                self.tags_to_save.apply_deezer(payload, conf);
+                self.tags_status = match score >= conf.tag_match_review {
+                    true => DzrsTrackObjectTagState::Matched,
+                    false => DzrsTrackObjectTagState::Successfull,
+                };
+                Ok(())
+            }
+            Some((_, score)) => {
+                self.tags_status = DzrsTrackObjectTagState::Unsuccessfull;
+                Err(format!(
+                    "No good enough match found on deezer for {}, best score {}",
+                    self.file_name, score
+                ))
+            }
+            None => {
+                self.tags_status = DzrsTrackObjectTagState::Unsuccessfull;
+                Err(format!("No tracks found on deezer for {}", self.file_name))
+            }
+        }
+    }
+
+    // Load metadata for this DzrsTrackObject using its file_path, replacing in-place
+    // the file is read based on its content, see audio::open_audio_file
+    // embedded pictures are left out, decoding them is what makes loading large folders slow
+    pub fn load_tags(&mut self, config: &DzrsConfigurationParsed) -> Result<(), String> {
+        let file = open_audio_file(&self.file_path)?;
+        let properties = file.read_properties();
+        let mut tags = file.read_tags(config);
+
+        // Set the length read from the file properties, readonly tag!
+        tags.length = properties.duration.to_string();
+
+        self.file_type = format!("{:?}", file.file_type());
+        self.audio_properties = properties;
+        self.tags = tags.clone();
+        self.tags_pictures.clear();
+        self.pictures_loaded = false;
+        self.tags_to_save = tags;
+        Ok(())
+    }
+}
+
+impl Deref for DzrsTrackObjectWrapper {
+    type Target = Vec<DzrsTrackObject>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.items
+    }
+}
+
+impl DerefMut for DzrsTrackObjectWrapper {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.items
+    }
+}
+
+pub struct DzrsTracksObjectWrapperIterator<'a> {
+    inner: std::slice::Iter<'a, DzrsTrackObject>,
+}
+
+impl<'a> DzrsTracksObjectWrapperIterator<'a> {
+    pub fn new(object: &'a DzrsTrackObjectWrapper) -> Self {
+        DzrsTracksObjectWrapperIterator {
+            inner: object.items.iter(),
+        }
+    }
+}
+
+impl<'a> Iterator for DzrsTracksObjectWrapperIterator<'a> {
+    type Item = &'a DzrsTrackObject;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl<'a> IntoIterator for &'a DzrsTrackObjectWrapper {
+    type Item = &'a DzrsTrackObject;
+    type IntoIter = DzrsTracksObjectWrapperIterator<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        DzrsTracksObjectWrapperIterator::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_match_literal() {
+        assert!(glob_match("node_modules", "node_modules"));
+        assert!(!glob_match("node_modules", "node_modules2"));
+        assert!(!glob_match("abc", "ab"));
+    }
+
+    #[test]
+    fn glob_match_star() {
+        assert!(glob_match("*.tmp", "track.tmp"));
+        assert!(glob_match("*.tmp", ".tmp"));
+        assert!(!glob_match("*.tmp", "track.tmp.flac"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYcZ"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("**", "anything"));
+    }
+
+    #[test]
+    fn glob_match_question_mark() {
+        assert!(glob_match("cd?", "cd1"));
+        assert!(!glob_match("cd?", "cd"));
+        assert!(!glob_match("cd?", "cd12"));
+        assert!(glob_match("?*", "\u{e9}"));
+    }
+
+    #[test]
+    fn glob_match_is_case_sensitive() {
+        assert!(!glob_match("Scans", "scans"));
+    }
+
+    #[test]
+    fn is_same_file_compares_files_not_paths() {
+        let dir = std::env::temp_dir().join(format!("dzrs-same-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.flac"), dir.join("b.flac"));
+        std::fs::write(&a, "a").unwrap();
+        std::fs::write(&b, "b").unwrap();
+        let same = is_same_file(&a, &dir.join(".").join("a.flac"));
+        let different = is_same_file(&a, &b);
+        let missing = is_same_file(&a, &dir.join("c.flac"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(same);
+        assert!(!different);
+        assert!(!missing);
+    }
+
+    fn track(path: &str) -> DzrsTrackObject {
+        DzrsTrackObject {
+            file_path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn apply_saved_follows_moved_files() {
+        let mut wrapper = DzrsTrackObjectWrapper {
+            items: vec![track("/music/in/a.flac"), track("/music/in/b.flac")],
+            root: "/music".to_string(),
+        };
+        let mut saved = track("/music/out/Artist/01 A.flac");
+        saved.file_name = "01 A.flac".to_string();
+        saved.file_size = 10;
+        wrapper.apply_saved("/music/in/a.flac", saved).unwrap();
+        let tr = wrapper.get_track_obj("/music/out/Artist/01 A.flac").unwrap();
+        assert_eq!(tr.file_name, "01 A.flac");
+        assert_eq!(Path::new(&tr.relative_path), Path::new("out/Artist/01 A.flac"));
+        assert_eq!(tr.file_size, 10);
+        assert!(wrapper.get_track_obj("/music/in/a.flac").is_none());
+    }
+
+    #[test]
+    fn apply_saved_drops_files_moved_outside_root() {
+        let mut wrapper = DzrsTrackObjectWrapper {
+            items: vec![track("/music/in/a.flac"), track("/music/in/b.flac")],
+            root: "/music/in".to_string(),
+        };
+        wrapper
+            .apply_saved("/music/in/a.flac", track("/music/out/a.flac"))
+            .unwrap();
+        assert_eq!(wrapper.items.len(), 1);
+        assert_eq!(wrapper.items[0].file_path, "/music/in/b.flac");
+        assert!(wrapper
+            .apply_saved("/music/in/a.flac", track("/music/in/a.flac"))
+            .is_err());
+    }
+
+    #[test]
+    fn copy_file_keeps_times_and_permissions() {
+        let dir = std::env::temp_dir().join(format!("dzrs-copy-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (from, to) = (dir.join("a.flac"), dir.join("b.flac"));
+        std::fs::write(&from, "a").unwrap();
+        let modified = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+        let file = OpenOptions::new().write(true).open(&from).unwrap();
+        file.set_times(FileTimes::new().set_modified(modified)).unwrap();
+        drop(file);
+        let mut permissions = std::fs::metadata(&from).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&from, permissions).unwrap();
+        let copied = copy_file(&from, &to).and_then(|_| std::fs::metadata(&to));
+        for path in [&from, &to] {
+            if let Ok(metadata) = std::fs::metadata(path) {
+                let mut permissions = metadata.permissions();
+                #[allow(clippy::permissions_set_readonly_false)]
+                permissions.set_readonly(false);
+                let _ = std::fs::set_permissions(path, permissions);
+            };
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        let copied = copied.unwrap();
+        assert_eq!(copied.modified().unwrap(), modified);
+        assert!(copied.permissions().readonly());
+    }
+}
@@ -0,0 +1,333 @@
+use crate::config::DzrsConfigurationParsed;
+use crate::types::audio::{open_audio_file, DzrsRawTag};
+
+use base64::{engine::general_purpose, Engine as _};
+use lofty::{MimeType, Picture, PictureType};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Journal of the writes made into files, before each write the tag and pictures of the file are backed up
+// so the write can be reverted later, either alone or along with every other write of the same batch
+// journal.json lists the entries while each backup is stored in its own <id>.json file
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DzrsHistory {
+    #[serde(skip)]
+    _dir: PathBuf,
+    entries: Vec<DzrsHistoryEntry>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DzrsHistoryEntry {
+    pub id: String,
+    // Writes made by the same operation, e.g. saving every modified file, share their batch
+    pub batch: String,
+    pub file_path: String,
+    // Seconds since the unix epoch
+    pub timestamp: u64,
+    // What the write was about, e.g. "save" or "revert"
+    pub operation: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct DzrsHistoryBackup {
+    // Written back as it is, so every field of the file is restored whatever the config
+    tag: DzrsRawTag,
+    pictures: Vec<DzrsHistoryPicture>,
+}
+
+// Pictures are kept exactly as found in the file, including the ones the picture panel cannot display
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct DzrsHistoryPicture {
+    // Numeric picture type, so undefined types are restored as well
+    pic_type: u8,
+    mime_type: String,
+    description: Option<String>,
+    b64: String,
+}
+
+impl DzrsHistory {
+    // Loads the journal found in dir, a missing or corrupted journal starts empty
+    pub fn load<P: AsRef<Path>>(dir: P) -> Self {
+        let dir = dir.as_ref();
+        let mut history = match File::open(dir.join("journal.json")) {
+            Ok(file) => serde_json::from_reader::<_, Self>(BufReader::new(file)).unwrap_or_default(),
+            Err(_) => Self::default(),
+        };
+        history._dir = dir.to_path_buf();
+        history
+    }
+
+    fn save(&self) -> Result<(), String> {
+        std::fs::create_dir_all(&self._dir).map_err(|err| err.to_string())?;
+        let tmp_path = self._dir.join("journal.json.tmp");
+        let file = File::create(&tmp_path).map_err(|err| err.to_string())?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &self).map_err(|err| err.to_string())?;
+        writer.flush().map_err(|err| err.to_string())?;
+        std::fs::rename(&tmp_path, self._dir.join("journal.json")).map_err(|err| err.to_string())
+    }
+
+    // Backs up the current tag and pictures of a file before writing into it, returns the id of the new entry
+    // once written prune has to be called, when the write failed the entry is dropped with discard instead
+    pub fn record(&mut self, path: &str, batch: &str, operation: &str) -> Result<String, String> {
+        let backup = read_backup(path)?;
+        self.push_entry(path, batch, operation, &backup)
+    }
+
+    fn push_entry(
+        &mut self,
+        path: &str,
+        batch: &str,
+        operation: &str,
+        backup: &DzrsHistoryBackup,
+    ) -> Result<String, String> {
+        let (timestamp, id) = (now_millis(), unique_id());
+        std::fs::create_dir_all(&self._dir).map_err(|err| err.to_string())?;
+        let json = serde_json::to_vec(backup).map_err(|err| err.to_string())?;
+        std::fs::write(self.backup_path(&id), json).map_err(|err| err.to_string())?;
+        self.entries.push(DzrsHistoryEntry {
+            id: id.clone(),
+            batch: batch.to_string(),
+            file_path: path.to_string(),
+            timestamp: (timestamp / 1000) as u64,
+            operation: operation.to_string(),
+        });
+        self.save()?;
+        Ok(id)
+    }
+
+    // Drops the entry recorded for a write which failed, along with its backup
+    pub fn discard(&mut self, id: &str) -> Result<(), String> {
+        self.entries.retain(|e| e.id != id);
+        let _ = std::fs::remove_file(self.backup_path(id));
+        self.save()
+    }
+
+    // Drops the entries above history_max_entries oldest first, called once the writes of an operation are done
+    // so the backups of a batch being reverted are never dropped halfway through
+    pub fn prune(&mut self, conf: &DzrsConfigurationParsed) -> Result<(), String> {
+        let max_entries = conf.history_max_entries as usize;
+        if self.entries.len() <= max_entries {
+            return Ok(());
+        };
+        let dropped: Vec<DzrsHistoryEntry> = self.entries.drain(..self.entries.len() - max_entries).collect();
+        for entry in dropped {
+            let _ = std::fs::remove_file(self.backup_path(&entry.id));
+        }
+        self.save()
+    }
+
+    // Follows a file moved after being written, e.g. by directory_move_on_save
+    pub fn set_path(&mut self, id: &str, path: &str) -> Result<(), String> {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.id == id) {
+            entry.file_path = path.to_string();
+        };
+        self.save()
+    }
+
+    // Entries of a file, most recent first
+    pub fn entries_for(&self, path: &str) -> Vec<DzrsHistoryEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|e| e.file_path == path)
+            .cloned()
+            .collect()
+    }
+
+    // Writes back the tag and pictures backed up by an entry, the current state is recorded as well
+    // so a revert can be reverted, returns the path of the reverted file
+    pub fn revert(&mut self, id: &str, batch: &str, conf: &DzrsConfigurationParsed) -> Result<String, String> {
+        let path = self.revert_entry(id, batch, conf)?;
+        self.prune(conf)?;
+        Ok(path)
+    }
+
+    // The current state is backed up before writing but only recorded once the write succeeded
+    fn revert_entry(&mut self, id: &str, batch: &str, conf: &DzrsConfigurationParsed) -> Result<String, String> {
+        let entry = match self.entries.iter().find(|e| e.id == id) {
+            Some(entry) => entry.clone(),
+            None => return Err(format!("Cannot find history entry {}", id)),
+        };
+        let file = File::open(self.backup_path(id)).map_err(|err| err.to_string())?;
+        let backup: DzrsHistoryBackup = serde_json::from_reader(BufReader::new(file)).map_err(|err| err.to_string())?;
+        let mut pictures = Vec::new();
+        for p in backup.pictures {
+            let data = general_purpose::STANDARD
+                .decode(&p.b64)
+                .map_err(|err| err.to_string())?;
+            pictures.push(Picture::new_unchecked(
+                PictureType::from_u8(p.pic_type),
+                MimeType::from_str(&p.mime_type),
+                p.description,
+                data,
+            ));
+        }
+
+        let current = read_backup(&entry.file_path)?;
+        let mut file = open_audio_file(&entry.file_path)?;
+        file.save_raw_tag(Path::new(&entry.file_path), &backup.tag, pictures, conf.tag_keep_mtime)?;
+        self.push_entry(&entry.file_path, batch, "revert", &current)?;
+        Ok(entry.file_path)
+    }
+
+    // Reverts every write of a batch, most recent first so files written more than once end up as before the batch
+    // Errors with a vector of each entry that could not be reverted
+    pub fn revert_batch(
+        &mut self,
+        batch: &str,
+        revert_batch: &str,
+        conf: &DzrsConfigurationParsed,
+    ) -> Result<Vec<String>, Vec<String>> {
+        let ids: Vec<String> = self
+            .entries
+            .iter()
+            .rev()
+            .filter(|e| e.batch == batch)
+            .map(|e| e.id.clone())
+            .collect();
+        if ids.is_empty() {
+            return Err(vec![format!("Cannot find history batch {}", batch)]);
+        };
+        let mut paths = Vec::new();
+        let mut errors = Vec::new();
+        for id in ids {
+            match self.revert_entry(&id, revert_batch, conf) {
+                Ok(path) => paths.push(path),
+                Err(err) => errors.push(err),
+            };
+        }
+        if let Err(err) = self.prune(conf) {
+            errors.push(err);
+        };
+        match errors.is_empty() {
+            true => Ok(paths),
+            false => Err(errors),
+        }
+    }
+
+    fn backup_path(&self, id: &str) -> PathBuf {
+        self._dir.join(format!("{}.json", id))
+    }
+}
+
+// Current tag and pictures of a file
+fn read_backup(path: &str) -> Result<DzrsHistoryBackup, String> {
+    let file = open_audio_file(path)?;
+    let pictures = file
+        .pictures()
+        .iter()
+        .map(|p| DzrsHistoryPicture {
+            pic_type: p.pic_type().as_u8(),
+            mime_type: p.mime_type().as_str().to_string(),
+            description: p.description().map(|d| d.to_string()),
+            b64: general_purpose::STANDARD.encode(p.data()),
+        })
+        .collect();
+    Ok(DzrsHistoryBackup {
+        tag: file.read_raw_tag(Path::new(path))?,
+        pictures,
+    })
+}
+
+// Identifies the writes of a single operation, e.g. a save of many files
+pub fn new_batch_id() -> String {
+    format!("batch-{}", unique_id())
+}
+
+// Ids made within the same millisecond are told apart by a counter, and by the process id when the app and the cli
+// write at the same time
+fn unique_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    format!(
+        "{}-{}-{}",
+        now_millis(),
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::audio::tests::{flac_bytes, FRAMES};
+    use crate::types::tags::DzrsTrackObjectTags;
+
+    #[test]
+    fn new_batch_id_is_unique_within_a_millisecond() {
+        let ids: Vec<String> = (0..1000).map(|_| new_batch_id()).collect();
+        let mut unique = ids.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), ids.len());
+        assert!(ids.iter().all(|id| id.starts_with("batch-")));
+    }
+
+    fn write_file(path: &Path, title: &str, pictures: Vec<Picture>) {
+        let conf = DzrsConfigurationParsed::default();
+        let tags = DzrsTrackObjectTags {
+            title: title.to_string(),
+            ..Default::default()
+        };
+        let mut file = open_audio_file(path).unwrap();
+        file.write_tags(&tags, &conf).unwrap();
+        file.set_pictures(pictures).unwrap();
+        file.save_safely(path, false).unwrap();
+    }
+
+    #[test]
+    fn revert_restores_tag_and_pictures() {
+        let dir = std::env::temp_dir().join(format!("dzrs-history-revert-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("track.flac");
+        std::fs::write(&path, flac_bytes("dzrs", FRAMES)).unwrap();
+        let picture = Picture::new_unchecked(PictureType::Undefined(42), MimeType::Png, None, vec![1, 2, 3]);
+        write_file(&path, "Before", vec![picture]);
+
+        let mut conf = DzrsConfigurationParsed {
+            history_max_entries: 10,
+            ..Default::default()
+        };
+        let mut history = DzrsHistory::load(dir.join("history"));
+        let id = history.record(path.to_str().unwrap(), "batch-1", "save").unwrap();
+        write_file(&path, "After", Vec::new());
+        history.revert(&id, "batch-2", &conf).unwrap();
+
+        let file = open_audio_file(&path).unwrap();
+        assert_eq!(file.read_tags(&conf).title, "Before");
+        let pictures = file.pictures();
+        assert_eq!(pictures.len(), 1);
+        assert_eq!(pictures[0].pic_type(), PictureType::Undefined(42));
+        assert_eq!(pictures[0].data(), &[1, 2, 3]);
+
+        // The revert is recorded as well, and reloaded from the journal
+        let mut history = DzrsHistory::load(dir.join("history"));
+        let entries = history.entries_for(path.to_str().unwrap());
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].operation, "revert");
+        history.discard(&entries[0].id).unwrap();
+        assert!(!history.backup_path(&entries[0].id).exists());
+        assert!(history.backup_path(&id).exists());
+        conf.history_max_entries = 0;
+        history.prune(&conf).unwrap();
+        assert!(history.entries_for(path.to_str().unwrap()).is_empty());
+        assert!(!history.backup_path(&id).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+use crate::types::files::DzrsTrackObject;
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+// Bumped whenever DzrsTrackObject changes in a way older indexes cannot be read into
+const INDEX_VERSION: u32 = 1;
+
+// On-disk index of the loaded files keyed by their path, unchanged files (same size and modification time)
+// are served from it instead of reading their tags again, it also keeps the fetched deezer data across restarts
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DzrsLibraryIndex {
+    #[serde(skip)]
+    _path: String,
+    version: u32,
+    items: HashMap<String, DzrsTrackObject>,
+}
+
+impl DzrsLibraryIndex {
+    // Loads the index from the given path, a missing, corrupted or outdated index starts empty
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let mut index = match File::open(&path) {
+            Ok(file) => serde_json::from_reader::<_, Self>(BufReader::new(file))
+                .ok()
+                .filter(|index| index.version == INDEX_VERSION)
+                .unwrap_or_default(),
+            Err(_) => Self::default(),
+        };
+        index._path = path.as_ref().to_str().unwrap_or_default().to_owned();
+        index.version = INDEX_VERSION;
+        index
+    }
+
+    // Writes the index into a temporary file which then replaces the previous one
+    // so an interrupted save never leaves a truncated index behind
+    pub fn save(&self) -> Result<(), String> {
+        let tmp_path = format!("{}.tmp", self._path);
+        let file = File::create(&tmp_path).map_err(|err| err.to_string())?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &self).map_err(|err| err.to_string())?;
+        writer.flush().map_err(|err| err.to_string())?;
+        std::fs::rename(&tmp_path, &self._path).map_err(|err| err.to_string())
+    }
+
+    // Returns the indexed DzrsTrackObject of a freshly scanned one, only when the file did not change since
+    pub fn get(&self, track: &DzrsTrackObject) -> Option<DzrsTrackObject> {
+        let indexed = self.items.get(&track.file_path)?;
+        if indexed.file_size != track.file_size || indexed.file_modified != track.file_modified {
+            return None;
+        };
+        let mut indexed = indexed.clone();
+        // The same file can be reached from a different root
+        indexed.relative_path = track.relative_path.clone();
+        Some(indexed)
+    }
+
+    // Replaces freshly scanned tracks with their indexed version when unchanged, the others are left to be loaded
+    pub fn restore(&self, tracks: &mut [DzrsTrackObject]) {
+        tracks.iter_mut().for_each(|track| {
+            if let Some(indexed) = self.get(track) {
+                *track = indexed;
+            };
+        });
+    }
+
+    // Stores the given tracks, indexed files within root which are not part of them anymore are dropped
+    // tracks whose tags were not loaded yet are skipped, pictures are never stored as they are read on demand
+    // and neither are fetched covers, which are fetched again along with the rest of the deezer data when needed
+    pub fn update(&mut self, root: &str, tracks: &[DzrsTrackObject]) {
+        if !root.is_empty() {
+            let paths: HashSet<&str> = tracks.iter().map(|tr| tr.file_path.as_str()).collect();
+            self.items
+                .retain(|path, _| !Path::new(path).starts_with(root) || paths.contains(path.as_str()));
+        };
+        for track in tracks.iter().filter(|tr| !tr.file_type.is_empty()) {
+            let mut track = track.clone();
+            track.tags_pictures.clear();
+            track.pictures_loaded = false;
+            track.tags.cover = None;
+            track.tags_deezer.cover = None;
+            track.tags_to_save.cover = None;
+            self.items.insert(track.file_path.clone(), track);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::tags::DzrsTrackObjectPicture;
+
+    fn track(path: &str, size: u64, modified: u64) -> DzrsTrackObject {
+        DzrsTrackObject {
+            file_path: path.to_string(),
+            relative_path: path.trim_start_matches("/music/").to_string(),
+            file_size: size,
+            file_modified: modified,
+            ..Default::default()
+        }
+    }
+
+    fn loaded(path: &str, size: u64, modified: u64) -> DzrsTrackObject {
+        let mut track = track(path, size, modified);
+        track.file_type = "Flac".to_string();
+        track.tags.title = format!("Title of {}", path);
+        track
+    }
+
+    #[test]
+    fn restore_serves_unchanged_files() {
+        let path = std::env::temp_dir().join(format!("dzrs-index-{}.json", std::process::id()));
+        let mut index = DzrsLibraryIndex::load(&path);
+        index.update(
+            "/music",
+            &[
+                loaded("/music/a.flac", 10, 100),
+                loaded("/music/b.flac", 10, 100),
+                loaded("/music/c.flac", 10, 100),
+                track("/music/d.flac", 10, 100),
+            ],
+        );
+        index.save().unwrap();
+        let index = DzrsLibraryIndex::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut scanned = vec![
+            track("/music/a.flac", 10, 100),
+            track("/music/b.flac", 11, 100),
+            track("/music/c.flac", 10, 101),
+            track("/music/d.flac", 10, 100),
+        ];
+        scanned[0].relative_path = "other/a.flac".to_string();
+        index.restore(&mut scanned);
+        assert_eq!(scanned[0].tags.title, "Title of /music/a.flac");
+        assert_eq!(scanned[0].relative_path, "other/a.flac");
+        // Changed size or modification time, or tags never loaded
+        assert!(scanned[1..]
+            .iter()
+            .all(|tr| tr.tags.title.is_empty() && tr.file_type.is_empty()));
+    }
+
+    #[test]
+    fn update_drops_missing_files_within_root_and_pictures() {
+        let mut index = DzrsLibraryIndex::default();
+        index.update("/other", &[loaded("/other/a.flac", 1, 1)]);
+        index.update(
+            "/music",
+            &[loaded("/music/a.flac", 1, 1), loaded("/music/b.flac", 1, 1)],
+        );
+        let mut track = loaded("/music/a.flac", 1, 1);
+        let cover = Some(DzrsTrackObjectPicture {
+            b64: "cover".to_string(),
+            ..Default::default()
+        });
+        track.tags_pictures = vec![DzrsTrackObjectPicture::default()];
+        track.pictures_loaded = true;
+        track.tags.cover = cover.clone();
+        track.tags_deezer.cover = cover.clone();
+        track.tags_to_save.cover = cover;
+        index.update("/music", &[track]);
+
+        let mut paths: Vec<&String> = index.items.keys().collect();
+        paths.sort();
+        assert_eq!(paths, vec!["/music/a.flac", "/other/a.flac"]);
+        let indexed = &index.items["/music/a.flac"];
+        assert!(indexed.tags_pictures.is_empty() && !indexed.pictures_loaded);
+        assert_eq!(
+            (
+                &indexed.tags.cover,
+                &indexed.tags_deezer.cover,
+                &indexed.tags_to_save.cover
+            ),
+            (&None, &None, &None)
+        );
+    }
+}
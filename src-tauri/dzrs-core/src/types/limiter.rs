@@ -0,0 +1,158 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Deezer allows 50 requests every 5 seconds
+const LIMITER_DEFAULT_RATE: u32 = 10;
+const LIMITER_DEFAULT_RETRIES: u32 = 3;
+// Waits between retries double from the base up to the max, quota errors reset after 5 seconds
+const RETRY_BASE_DELAY_MS: u64 = 1000;
+const RETRY_MAX_DELAY_MS: u64 = 8000;
+
+// Token bucket shared by every request to deezer, holding up to a second worth of requests so short bursts
+// go out at once while longer runs settle at the configured rate, a rate of 0 disables the limit
+#[derive(Debug)]
+pub struct DzrsRateLimiter {
+    state: Mutex<DzrsLimiterState>,
+}
+
+#[derive(Debug)]
+struct DzrsLimiterState {
+    rate: u32,
+    max_retries: u32,
+    // Goes below zero when requests are waiting for their turn
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl DzrsRateLimiter {
+    pub fn new(rate: u32, max_retries: u32) -> Self {
+        Self {
+            state: Mutex::new(DzrsLimiterState {
+                rate,
+                max_retries,
+                tokens: rate as f64,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn configure(&self, rate: u32, max_retries: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.tokens = state.tokens.min(rate as f64);
+        state.rate = rate;
+        state.max_retries = max_retries;
+    }
+
+    pub fn max_retries(&self) -> u32 {
+        self.state.lock().unwrap().max_retries
+    }
+
+    // Waits until a request is allowed, each caller reserves its token right away so waiting requests go out
+    // in order instead of racing for the next one
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            if state.rate == 0 {
+                return;
+            };
+            let rate = state.rate as f64;
+            let now = Instant::now();
+            let elapsed = now.duration_since(state.refilled).as_secs_f64();
+            state.tokens = (state.tokens + elapsed * rate).min(rate) - 1.0;
+            state.refilled = now;
+            match state.tokens < 0.0 {
+                true => Duration::from_secs_f64(-state.tokens / rate),
+                false => Duration::ZERO,
+            }
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        };
+    }
+}
+
+impl Default for DzrsRateLimiter {
+    fn default() -> Self {
+        Self::new(LIMITER_DEFAULT_RATE, LIMITER_DEFAULT_RETRIES)
+    }
+}
+
+// Delay before the given retry, starting at 1
+pub fn retry_delay(retry: u32) -> Duration {
+    let delay = RETRY_BASE_DELAY_MS.saturating_mul(1 << retry.saturating_sub(1).min(16));
+    Duration::from_millis(delay.min(RETRY_MAX_DELAY_MS))
+}
+
+// Errors worth retrying, as the deezer client only hands out its errors as text they are told apart by their message
+// quota exceeded (code 4) and service busy (code 700) errors from deezer, timeouts, dropped connections,
+// too many requests and server errors
+pub fn is_retryable(err: &str) -> bool {
+    let err = err.to_lowercase().replace(' ', "");
+    [
+        "quota",
+        "\"code\":4}",
+        "\"code\":4,",
+        "\"code\":700",
+        "servicebusy",
+        "timedout",
+        "timeout",
+        "connect",
+        "toomanyrequests",
+        "servererror",
+    ]
+    .iter()
+    .any(|pattern| err.contains(pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_max() {
+        assert_eq!(retry_delay(0), Duration::from_millis(1000));
+        assert_eq!(retry_delay(1), Duration::from_millis(1000));
+        assert_eq!(retry_delay(2), Duration::from_millis(2000));
+        assert_eq!(retry_delay(3), Duration::from_millis(4000));
+        assert_eq!(retry_delay(4), Duration::from_millis(8000));
+        assert_eq!(retry_delay(5), Duration::from_millis(8000));
+        assert_eq!(retry_delay(u32::MAX), Duration::from_millis(8000));
+    }
+
+    #[test]
+    fn is_retryable_deezer_errors() {
+        assert!(is_retryable(
+            r#"{"error":{"type":"Exception","message":"Quota limit exceeded","code":4}}"#
+        ));
+        assert!(is_retryable(r#"{"error": {"code": 4, "message": "Too many"}}"#));
+        assert!(is_retryable(r#"{"error":{"code":700,"message":"Service busy"}}"#));
+    }
+
+    #[test]
+    fn is_retryable_network_errors() {
+        assert!(is_retryable("operation timed out"));
+        assert!(is_retryable("request Timeout"));
+        assert!(is_retryable("error trying to connect: dns error"));
+        assert!(is_retryable("HTTP status client error (429 Too Many Requests)"));
+        assert!(is_retryable("HTTP status server error (502 Bad Gateway)"));
+    }
+
+    #[test]
+    fn is_retryable_other_errors() {
+        assert!(!is_retryable(
+            r#"{"error":{"type":"DataException","message":"no data","code":800}}"#
+        ));
+        // Code 4 only counts as a whole code, not as the start of another one
+        assert!(!is_retryable(r#"{"error":{"code":40}}"#));
+        assert!(!is_retryable("HTTP status client error (404 Not Found)"));
+        assert!(!is_retryable(""));
+    }
+
+    #[test]
+    fn configure_keeps_tokens_within_the_new_rate() {
+        let limiter = DzrsRateLimiter::new(10, 3);
+        limiter.configure(2, 5);
+        assert_eq!(limiter.max_retries(), 5);
+        assert_eq!(limiter.state.lock().unwrap().tokens, 2.0);
+    }
+}
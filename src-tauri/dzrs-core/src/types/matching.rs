@@ -0,0 +1,284 @@
+use crate::types::tags::DzrsTrackObjectTags;
+
+use deezerapi_rs::models::api as deezer_api;
+use regex::Regex;
+use std::sync::OnceLock;
+
+// Deezer track compared against a local file, search results leave the track and disk numbers and isrc empty
+// as these are only known once the full track is fetched
+#[derive(Clone, Debug, Default)]
+pub struct DzrsMatchCandidate {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub duration: u64,
+    pub track_number: String,
+    pub disk_number: String,
+    pub isrc: String,
+}
+
+impl From<&deezer_api::Track> for DzrsMatchCandidate {
+    fn from(value: &deezer_api::Track) -> Self {
+        Self {
+            title: value.title.clone(),
+            artist: value.artist.name.clone(),
+            album: value.album.title.clone(),
+            duration: value.duration,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&deezer_api::MainTrack> for DzrsMatchCandidate {
+    fn from(value: &deezer_api::MainTrack) -> Self {
+        Self {
+            title: value.title.clone(),
+            artist: value.artist.name.clone(),
+            album: value.album.title.clone(),
+            duration: value.duration,
+            track_number: value.track_position.to_string(),
+            disk_number: value.disk_number.to_string(),
+            isrc: value.isrc.clone(),
+        }
+    }
+}
+
+// Scores from 0 to 100 how well a candidate matches the tags and duration in seconds of a local file
+// every criterion known on both sides counts with its weight, the same isrc identifies the recording outright
+pub fn match_score(tags: &DzrsTrackObjectTags, duration: u64, candidate: &DzrsMatchCandidate) -> u8 {
+    let (isrc, candidate_isrc) = (normalize(&tags.isrc), normalize(&candidate.isrc));
+    if !isrc.is_empty() && isrc == candidate_isrc {
+        return 100;
+    };
+
+    let mut criteria: Vec<(f64, f64)> = Vec::new();
+    if duration > 0 && candidate.duration > 0 {
+        criteria.push((0.3, duration_similarity(duration, candidate.duration)));
+    };
+    if !tags.title.is_empty() {
+        criteria.push((
+            0.3,
+            text_similarity(&clean_title(&tags.title), &clean_title(&candidate.title)),
+        ));
+    };
+    if !tags.artist.is_empty() {
+        let similarity = tags
+            .artist
+            .iter()
+            .map(|a| text_similarity(a, &candidate.artist))
+            .fold(0.0, f64::max);
+        criteria.push((0.2, similarity));
+    };
+    if !tags.album.is_empty() {
+        criteria.push((
+            0.1,
+            text_similarity(&clean_album(&tags.album), &clean_album(&candidate.album)),
+        ));
+    };
+    for (local, remote) in [
+        (&tags.track_number, &candidate.track_number),
+        (&tags.disk_number, &candidate.disk_number),
+    ] {
+        if let (Some(a), Some(b)) = (leading_number(local), leading_number(remote)) {
+            criteria.push((0.05, if a == b { 1.0 } else { 0.0 }));
+        };
+    }
+    // Different isrcs mean a different recording, e.g. a live or remastered version
+    if !isrc.is_empty() && !candidate_isrc.is_empty() {
+        criteria.push((0.2, 0.0));
+    };
+
+    let total: f64 = criteria.iter().map(|(w, _)| w).sum();
+    if total == 0.0 {
+        return 0;
+    };
+    let score: f64 = criteria.iter().map(|(w, s)| w * s).sum::<f64>() / total;
+    (score * 100.0).round() as u8
+}
+
+// Full score within 2 seconds, decreasing down to nothing at 15 seconds apart
+fn duration_similarity(a: u64, b: u64) -> f64 {
+    let diff = a.abs_diff(b) as f64;
+    (1.0 - (diff - 2.0).max(0.0) / 13.0).max(0.0)
+}
+
+// Dice coefficient over the character bigrams of the normalized texts, a text contained in the other one
+// e.g. "Song" and "Song Remastered 2011", still scores high
+pub fn text_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize(a), normalize(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    };
+    if a == b {
+        return 1.0;
+    };
+    let containment = match a.len().min(b.len()) >= 3 && (a.contains(&b) || b.contains(&a)) {
+        true => 0.85,
+        false => 0.0,
+    };
+    let bigrams = |s: &str| -> Vec<(char, char)> {
+        let chars: Vec<char> = s.chars().collect();
+        chars.windows(2).map(|w| (w[0], w[1])).collect()
+    };
+    let (a, mut b) = (bigrams(&a), bigrams(&b));
+    let total = a.len() + b.len();
+    if total == 0 {
+        return containment;
+    };
+    let mut shared = 0;
+    for bigram in &a {
+        if let Some(i) = b.iter().position(|x| x == bigram) {
+            b.swap_remove(i);
+            shared += 1;
+        };
+    }
+    f64::max(containment, 2.0 * shared as f64 / total as f64)
+}
+
+// Lowercase words of letters and digits separated by single spaces
+fn normalize(value: &str) -> String {
+    value
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+// Featured artists are left out of titles, as local files and deezer rarely agree on them
+// regexes are compiled once, since every candidate of every file gets cleaned, as well as the search queries
+pub fn clean_title(title: &str) -> String {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"(?i)[\[\(][^\]\)]*?(?:with|feat|ft\.)[^\]\)]*[\]\)]").unwrap());
+    re.replace_all(title, "").to_string()
+}
+
+// Explicit mentions are left out of albums
+pub fn clean_album(album: &str) -> String {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"[\[\(]?(?i:explicit)[\]\)]?").unwrap());
+    re.replace_all(album, "").to_string()
+}
+
+// Track and disk numbers may be written as "03" or "3/12"
+pub fn leading_number(value: &str) -> Option<u32> {
+    let digits: String = value.trim().chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok().filter(|n| *n > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(title: &str, artist: &str, isrc: &str) -> DzrsTrackObjectTags {
+        DzrsTrackObjectTags {
+            title: title.to_string(),
+            artist: vec![artist.to_string()],
+            isrc: isrc.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn candidate(title: &str, artist: &str, duration: u64, isrc: &str) -> DzrsMatchCandidate {
+        DzrsMatchCandidate {
+            title: title.to_string(),
+            artist: artist.to_string(),
+            duration,
+            isrc: isrc.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn match_score_same_isrc_is_full_match() {
+        let score = match_score(
+            &tags("", "", "USRC17607839"),
+            0,
+            &candidate("Other", "", 0, "usrc17607839"),
+        );
+        assert_eq!(score, 100);
+    }
+
+    #[test]
+    fn match_score_different_isrc_is_penalized() {
+        let score = match_score(
+            &tags("Song", "Artist", "USRC17607839"),
+            200,
+            &candidate("Song", "Artist", 200, "GBAYE0601498"),
+        );
+        assert_eq!(score, 80);
+    }
+
+    #[test]
+    fn match_score_ignores_featured_artists() {
+        let score = match_score(
+            &tags("Song (feat. Someone)", "Artist", ""),
+            200,
+            &candidate("Song", "Artist", 201, ""),
+        );
+        assert_eq!(score, 100);
+    }
+
+    #[test]
+    fn match_score_without_criteria_is_zero() {
+        assert_eq!(
+            match_score(&DzrsTrackObjectTags::default(), 0, &candidate("Song", "Artist", 0, "")),
+            0
+        );
+    }
+
+    #[test]
+    fn match_score_compares_track_numbers() {
+        let mut local = tags("Song", "Artist", "");
+        local.track_number = "03/12".to_string();
+        let mut remote = candidate("Song", "Artist", 200, "");
+        remote.track_number = "3".to_string();
+        assert_eq!(match_score(&local, 200, &remote), 100);
+        remote.track_number = "4".to_string();
+        assert!(match_score(&local, 200, &remote) < 100);
+    }
+
+    #[test]
+    fn text_similarity_normalizes_case_and_punctuation() {
+        assert_eq!(text_similarity("Hello, World!", "hello   world"), 1.0);
+    }
+
+    #[test]
+    fn text_similarity_empty_is_zero() {
+        assert_eq!(text_similarity("", ""), 0.0);
+        assert_eq!(text_similarity("Song", "!!"), 0.0);
+    }
+
+    #[test]
+    fn text_similarity_contained_text_scores_high() {
+        assert!(text_similarity("Song", "Song - Remastered 2011") >= 0.85);
+        // Too short to count as contained
+        assert!(text_similarity("so", "Song") < 0.85);
+    }
+
+    #[test]
+    fn text_similarity_unrelated_is_zero() {
+        assert_eq!(text_similarity("abc", "xyz"), 0.0);
+    }
+
+    #[test]
+    fn duration_similarity_bounds() {
+        assert_eq!(duration_similarity(200, 202), 1.0);
+        assert_eq!(duration_similarity(202, 200), 1.0);
+        assert_eq!(duration_similarity(200, 215), 0.0);
+        assert_eq!(duration_similarity(200, 1000), 0.0);
+        let half = duration_similarity(200, 208);
+        assert!(half > 0.0 && half < 1.0);
+    }
+
+    #[test]
+    fn leading_number_parses_prefix() {
+        assert_eq!(leading_number("03"), Some(3));
+        assert_eq!(leading_number("3/12"), Some(3));
+        assert_eq!(leading_number(" 7 "), Some(7));
+        assert_eq!(leading_number("0"), None);
+        assert_eq!(leading_number(""), None);
+        assert_eq!(leading_number("A1"), None);
+        assert_eq!(leading_number("99999999999"), None);
+    }
+}
@@ -0,0 +1,12 @@
+pub mod album;
+pub mod audio;
+pub mod cache;
+pub mod diff;
+pub mod files;
+pub mod history;
+pub mod index;
+pub mod limiter;
+pub mod matching;
+pub mod tags;
+pub mod template;
+pub mod trash;
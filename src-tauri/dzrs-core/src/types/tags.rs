@@ -0,0 +1,1729 @@
+use crate::config::DzrsConfigurationParsed;
+use crate::types::album::DzrsAlbumSource;
+use crate::types::cache::DzrsResponseCache;
+use crate::types::limiter::{is_retryable, retry_delay, DzrsRateLimiter};
+use crate::types::matching::{leading_number, match_score, DzrsMatchCandidate};
+
+use base64::{engine::general_purpose, Engine as _};
+use deezerapi_rs::models::{api as deezer_api, gw as deezer_gw};
+use deezerapi_rs::Deezer;
+use lofty::id3::v2::{
+    Frame, FrameFlags, FrameValue, Id3v2Tag, SyncTextContentType, SyncTextInformation, SynchronizedText,
+    TextInformationFrame, TimestampFormat, UnsynchronizedTextFrame,
+};
+use lofty::mp4::{AdvisoryRating, Atom, AtomData, AtomIdent, Ilst};
+use lofty::ogg::VorbisComments;
+use lofty::{Accessor, TextEncoding};
+use lofty::{Picture, PictureInformation, PictureType};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+
+pub fn set_vorbis_tags(tags: &DzrsTrackObjectTags, vorbis: &mut VorbisComments, conf: &DzrsConfigurationParsed) {
+    let tags = tags.clone();
+    vorbis.set_title(tags.title);
+    set_vorbis_values(vorbis, "ARTIST", tags.artist, conf);
+    vorbis.set_album(tags.album);
+    set_vorbis_values(vorbis, "GENRE", tags.genre, conf);
+    vorbis.set_comment(tags.comment);
+    vorbis.set_track(tags.track_number.parse().unwrap_or_default());
+    vorbis.set_track_total(tags.track_total.parse().unwrap_or_default());
+    vorbis.set_disk(tags.disk_number.parse().unwrap_or_default());
+    vorbis.set_disk_total(tags.disk_total.parse().unwrap_or_default());
+    vorbis.insert("ALBUMARTIST".to_string(), tags.album_artist);
+    set_vorbis_values(vorbis, "COMPOSER", tags.composer, conf);
+    set_vorbis_values(vorbis, "PERFORMER", tags.performer, conf);
+    set_vorbis_values(vorbis, "PRODUCER", tags.producer, conf);
+    vorbis.insert("LYRICS".to_string(), tags.lyrics);
+    vorbis.insert("COPYRIGHT".to_string(), tags.copyright);
+    vorbis.insert("DESCRIPTION".to_string(), tags.description);
+    vorbis.insert("DATE".to_string(), tags.date);
+    vorbis.insert("YEAR".to_string(), tags.year);
+    vorbis.insert("ORIGINALDATE".to_string(), tags.original_date);
+    set_vorbis_values(vorbis, "LABEL", tags.label, conf);
+    set_vorbis_values(vorbis, "ORGANIZATION", tags.organization, conf);
+    vorbis.insert("BARCODE".to_string(), tags.barcode);
+    vorbis.insert("ISRC".to_string(), tags.isrc);
+    vorbis.insert("BPM".to_string(), tags.bpm);
+    vorbis.insert("ITUNESADVISORY".to_string(), tags.explicit);
+    vorbis.insert("REPLAYGAIN_ALBUM_GAIN".to_string(), tags.replaygain_album_gain);
+    vorbis.insert("REPLAYGAIN_ALBUM_PEAK".to_string(), tags.replaygain_album_peak);
+    vorbis.insert("REPLAYGAIN_TRACK_GAIN".to_string(), tags.replaygain_track_gain);
+    vorbis.insert("REPLAYGAIN_TRACK_PEAK".to_string(), tags.replaygain_track_peak);
+    vorbis.insert("SOURCEID".to_string(), tags.source_id);
+    vorbis.insert("ENCODER".to_string(), tags.encoder);
+    // extra_tags holds every other field of the file, fields missing from it were removed or renamed
+    let current_extra_tags = DzrsTrackObjectTags::new(vorbis).extra_tags;
+    for item in current_extra_tags.iter() {
+        if conf.tag_clear_extra_tags || !tags.extra_tags.iter().any(|t| t.0 == item.0) {
+            let _ = vorbis.remove(&item.0);
+        };
+    }
+    if !conf.tag_clear_extra_tags {
+        // Each key is rewritten as a whole, so repeated and newly added keys are kept
+        let mut written: Vec<&str> = Vec::new();
+        for item in tags.extra_tags.iter() {
+            if !written.contains(&item.0.as_str()) {
+                let _ = vorbis.remove(&item.0);
+                written.push(&item.0);
+            };
+            vorbis.push(item.0.clone(), item.1.clone());
+        }
+    }
+}
+
+// Multi valued fields are written as one field per value, as recommended by the Vorbis comment spec,
+// or as a single field joined with the separator when tag_multiple_values is disabled
+fn set_vorbis_values(vorbis: &mut VorbisComments, key: &str, values: Vec<String>, conf: &DzrsConfigurationParsed) {
+    let _ = vorbis.remove(key);
+    if conf.tag_multiple_values {
+        for value in values.into_iter().filter(|v| !v.is_empty()) {
+            vorbis.push(key.to_string(), value);
+        }
+    } else {
+        vorbis.insert(key.to_string(), values.join(&conf.tag_separator));
+    }
+}
+
+// ID3v2 frames mapped to a DzrsTrackObjectTags field, TXXX frames are identified by their description
+const ID3V2_TEXT_FRAMES: [&str; 19] = [
+    "TIT2", "TPE1", "TALB", "TPE2", "TCOM", "TCON", "TCOP", "TRCK", "TPOS", "TDRC", "TDOR", "TPUB", "TSRC", "TBPM",
+    "TSSE", "COMM", "USLT", "SYLT", "APIC",
+];
+const ID3V2_USER_TEXT_FRAMES: [&str; 11] = [
+    "PERFORMER",
+    "PRODUCER",
+    "DESCRIPTION",
+    "ORGANIZATION",
+    "BARCODE",
+    "ITUNESADVISORY",
+    "REPLAYGAIN_ALBUM_GAIN",
+    "REPLAYGAIN_ALBUM_PEAK",
+    "REPLAYGAIN_TRACK_GAIN",
+    "REPLAYGAIN_TRACK_PEAK",
+    "SOURCEID",
+];
+
+pub fn set_id3v2_tags(tags: &DzrsTrackObjectTags, id3: &mut Id3v2Tag, conf: &DzrsConfigurationParsed) {
+    let tags = tags.clone();
+    id3.set_title(tags.title);
+    set_id3v2_text(id3, "TPE1", join_id3v2_values(tags.artist, conf));
+    id3.set_album(tags.album);
+    set_id3v2_text(id3, "TCON", join_id3v2_values(tags.genre, conf));
+    id3.set_comment(tags.comment);
+    // TRCK and TPOS are written as "number/total" to keep any padding of the values
+    set_id3v2_text(id3, "TRCK", join_id3v2_pair(tags.track_number, tags.track_total));
+    set_id3v2_text(id3, "TPOS", join_id3v2_pair(tags.disk_number, tags.disk_total));
+    set_id3v2_text(id3, "TPE2", tags.album_artist);
+    set_id3v2_text(id3, "TCOM", join_id3v2_values(tags.composer, conf));
+    set_id3v2_text(id3, "TCOP", tags.copyright);
+    // ID3v2.4 has no separate year frame, TDRC holds the full date and the year is derived from it
+    match tags.date.is_empty() {
+        true => set_id3v2_text(id3, "TDRC", tags.year),
+        false => set_id3v2_text(id3, "TDRC", tags.date),
+    };
+    set_id3v2_text(id3, "TDOR", tags.original_date);
+    set_id3v2_text(id3, "TPUB", join_id3v2_values(tags.label, conf));
+    set_id3v2_text(id3, "TSRC", tags.isrc);
+    set_id3v2_text(id3, "TBPM", tags.bpm);
+    set_id3v2_text(id3, "TSSE", tags.encoder);
+    set_id3v2_mapped_user_text(id3, "PERFORMER", join_id3v2_values(tags.performer, conf));
+    set_id3v2_mapped_user_text(id3, "PRODUCER", join_id3v2_values(tags.producer, conf));
+    set_id3v2_mapped_user_text(id3, "DESCRIPTION", tags.description);
+    set_id3v2_mapped_user_text(id3, "ORGANIZATION", join_id3v2_values(tags.organization, conf));
+    set_id3v2_mapped_user_text(id3, "BARCODE", tags.barcode);
+    set_id3v2_mapped_user_text(id3, "ITUNESADVISORY", tags.explicit);
+    set_id3v2_mapped_user_text(id3, "REPLAYGAIN_ALBUM_GAIN", tags.replaygain_album_gain);
+    set_id3v2_mapped_user_text(id3, "REPLAYGAIN_ALBUM_PEAK", tags.replaygain_album_peak);
+    set_id3v2_mapped_user_text(id3, "REPLAYGAIN_TRACK_GAIN", tags.replaygain_track_gain);
+    set_id3v2_mapped_user_text(id3, "REPLAYGAIN_TRACK_PEAK", tags.replaygain_track_peak);
+    set_id3v2_mapped_user_text(id3, "SOURCEID", tags.source_id);
+    set_id3v2_lyrics(id3, tags.lyrics);
+    // extra_tags holds every other frame of the file, frames missing from it were removed or renamed
+    let current_extra_tags = DzrsTrackObjectTags::from_id3v2(id3, conf).extra_tags;
+    for item in current_extra_tags.iter() {
+        if conf.tag_clear_extra_tags || !tags.extra_tags.iter().any(|t| t.0 == item.0) {
+            remove_id3v2_extra(id3, &item.0);
+        };
+    }
+    if !conf.tag_clear_extra_tags {
+        // Repeated keys are written as a single multi valued frame, mapped frames would overwrite the fields above
+        let mut written: Vec<&str> = Vec::new();
+        for item in tags.extra_tags.iter() {
+            if !written.contains(&item.0.as_str()) && !is_id3v2_mapped(&item.0) {
+                let values: Vec<&str> = extra_tag_values(&tags.extra_tags, &item.0);
+                set_id3v2_extra(id3, &item.0, values.join("\0"));
+                written.push(&item.0);
+            };
+        }
+    }
+}
+
+// Replaces a text information frame, empty values remove the frame
+fn set_id3v2_text(id3: &mut Id3v2Tag, id: &str, value: String) {
+    let _ = id3.remove(id);
+    if value.is_empty() {
+        return;
+    }
+    let value = FrameValue::Text(TextInformationFrame {
+        encoding: TextEncoding::UTF8,
+        value,
+    });
+    if let Ok(frame) = Frame::new(id.to_string(), value, FrameFlags::default()) {
+        id3.insert(frame);
+    };
+}
+
+// Replaces a TXXX frame identified by its description, empty values remove the frame
+fn set_id3v2_user_text(id3: &mut Id3v2Tag, description: &str, value: String) {
+    let _ = id3.remove_user_text(description);
+    if !value.is_empty() {
+        id3.insert_user_text(description.to_string(), value);
+    };
+}
+
+// Mapped TXXX frames are matched ignoring the case of their description, as other taggers write e.g. "Barcode",
+// every matching frame is replaced by a single one with the uppercase description
+fn set_id3v2_mapped_user_text(id3: &mut Id3v2Tag, description: &str, value: String) {
+    let descriptions: Vec<String> = id3
+        .iter()
+        .filter_map(|frame| match frame.content() {
+            FrameValue::UserText(f) if f.description.eq_ignore_ascii_case(description) => Some(f.description.clone()),
+            _ => None,
+        })
+        .collect();
+    for d in descriptions {
+        let _ = id3.remove_user_text(&d);
+    }
+    set_id3v2_user_text(id3, description, value);
+}
+
+// Value of a mapped TXXX frame, see set_id3v2_mapped_user_text
+fn get_id3v2_mapped_user_text<'a>(id3: &'a Id3v2Tag, description: &str) -> Option<&'a str> {
+    id3.iter().find_map(|frame| match frame.content() {
+        FrameValue::UserText(f) if f.description.eq_ignore_ascii_case(description) => Some(f.content.as_str()),
+        _ => None,
+    })
+}
+
+// Writes lyrics as USLT, timestamped (lrc) lyrics are additionally written as SYLT
+fn set_id3v2_lyrics(id3: &mut Id3v2Tag, lyrics: String) {
+    let _ = id3.remove("USLT");
+    let _ = id3.remove("SYLT");
+    if lyrics.is_empty() {
+        return;
+    }
+    if let Some(lines) = parse_lrc(&lyrics) {
+        let sylt = SynchronizedText {
+            information: SyncTextInformation {
+                encoding: TextEncoding::UTF8,
+                language: *b"XXX",
+                timestamp_format: TimestampFormat::MS,
+                content_type: SyncTextContentType::Lyrics,
+                description: None,
+            },
+            content: lines,
+        };
+        if let Ok(data) = sylt.as_bytes() {
+            if let Ok(frame) = Frame::new("SYLT", FrameValue::Binary(data), FrameFlags::default()) {
+                id3.insert(frame);
+            };
+        };
+    };
+    let uslt = FrameValue::UnsynchronizedText(UnsynchronizedTextFrame {
+        encoding: TextEncoding::UTF8,
+        language: *b"XXX",
+        description: String::new(),
+        content: lyrics,
+    });
+    if let Ok(frame) = Frame::new("USLT", uslt, FrameFlags::default()) {
+        id3.insert(frame);
+    };
+}
+
+// Extra tags are keyed by frame id, or by description for TXXX frames
+// nonstandard text frames read from the file are rewritten as TXXX
+fn set_id3v2_extra(id3: &mut Id3v2Tag, key: &str, value: String) {
+    match is_id3v2_frame_id(key) {
+        true => set_id3v2_text(id3, key, value),
+        false => {
+            remove_id3v2_nonstandard_text(id3, key);
+            set_id3v2_user_text(id3, key, value)
+        }
+    };
+}
+
+fn remove_id3v2_extra(id3: &mut Id3v2Tag, key: &str) {
+    match is_id3v2_frame_id(key) {
+        true => {
+            let _ = id3.remove(key);
+        }
+        false => {
+            remove_id3v2_nonstandard_text(id3, key);
+            let _ = id3.remove_user_text(key);
+        }
+    };
+}
+
+// Nonstandard text frames are keyed by their id as well, other frames such as APIC are never touched
+fn remove_id3v2_nonstandard_text(id3: &mut Id3v2Tag, key: &str) {
+    let is_text_frame = key.len() == 4
+        && key.starts_with('T')
+        && key != "TXXX"
+        && key.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+    if is_text_frame {
+        let _ = id3.remove(key);
+    };
+}
+
+// ID3v2.4 separates the values of a text frame with null characters
+fn join_id3v2_values(values: Vec<String>, conf: &DzrsConfigurationParsed) -> String {
+    match conf.tag_multiple_values {
+        true => values.join("\0"),
+        false => values.join(&conf.tag_separator),
+    }
+}
+
+fn split_id3v2_values(value: &str) -> Vec<String> {
+    value
+        .split('\0')
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_string())
+        .collect()
+}
+
+fn join_id3v2_pair(number: String, total: String) -> String {
+    match (number.is_empty(), total.is_empty()) {
+        (_, true) => number,
+        (true, false) => format!("0/{}", total),
+        (false, false) => format!("{}/{}", number, total),
+    }
+}
+
+fn split_id3v2_pair(value: &str) -> (String, String) {
+    match value.split_once('/') {
+        Some((number, total)) => (number.trim().to_string(), total.trim().to_string()),
+        None => (value.trim().to_string(), String::new()),
+    }
+}
+
+// Text information frames of ID3v2.4, along with the widespread iTunes ones (TCMP, TSO2 and TSOC)
+const ID3V2_FRAME_IDS: [&str; 48] = [
+    "TALB", "TBPM", "TCMP", "TCOM", "TCON", "TCOP", "TDEN", "TDLY", "TDOR", "TDRC", "TDRL", "TDTG", "TENC", "TEXT",
+    "TFLT", "TIPL", "TIT1", "TIT2", "TIT3", "TKEY", "TLAN", "TLEN", "TMCL", "TMED", "TMOO", "TOAL", "TOFN", "TOLY",
+    "TOPE", "TOWN", "TPE1", "TPE2", "TPE3", "TPE4", "TPOS", "TPRO", "TPUB", "TRCK", "TRSN", "TRSO", "TSO2", "TSOA",
+    "TSOC", "TSOP", "TSOT", "TSRC", "TSSE", "TSST",
+];
+
+// Only known text information frames are handled as extra tags by their id, any other key is stored as TXXX
+fn is_id3v2_frame_id(key: &str) -> bool {
+    ID3V2_FRAME_IDS.contains(&key)
+}
+
+// Frames holding a DzrsTrackObjectTags field, see ID3V2_TEXT_FRAMES
+fn is_id3v2_mapped(key: &str) -> bool {
+    ID3V2_TEXT_FRAMES.contains(&key) || ID3V2_USER_TEXT_FRAMES.contains(&key.to_uppercase().as_str())
+}
+
+// Freeform atoms written under the com.apple.iTunes mean, mapped to a DzrsTrackObjectTags field
+const ILST_ITUNES_ATOMS: [&str; 12] = [
+    "PERFORMER",
+    "PRODUCER",
+    "ORIGINALDATE",
+    "LABEL",
+    "ORGANIZATION",
+    "BARCODE",
+    "ISRC",
+    "REPLAYGAIN_ALBUM_GAIN",
+    "REPLAYGAIN_ALBUM_PEAK",
+    "REPLAYGAIN_TRACK_GAIN",
+    "REPLAYGAIN_TRACK_PEAK",
+    "SOURCEID",
+];
+const ILST_ATOMS: [&str; 17] = [
+    "©nam", "©ART", "©alb", "aART", "©wrt", "©gen", "©cmt", "©lyr", "cprt", "desc", "©day", "trkn", "disk", "tmpo",
+    "rtng", "©too", "covr",
+];
+
+pub fn set_ilst_tags(tags: &DzrsTrackObjectTags, ilst: &mut Ilst, conf: &DzrsConfigurationParsed) {
+    let tags = tags.clone();
+    // Multi valued atoms are poorly supported by players, so values are always joined with the separator
+    let sep = conf.tag_separator.as_str();
+    ilst.set_title(tags.title);
+    set_ilst_text(ilst, fourcc("©ART"), tags.artist.join(sep));
+    ilst.set_album(tags.album);
+    set_ilst_text(ilst, fourcc("©gen"), tags.genre.join(sep));
+    ilst.set_comment(tags.comment);
+    // trkn and disk are stored as integers, empty or invalid values remove them
+    match tags.track_number.parse::<u32>() {
+        Ok(n) => ilst.set_track(n),
+        Err(_) => ilst.remove_track(),
+    };
+    match tags.track_total.parse::<u32>() {
+        Ok(n) => ilst.set_track_total(n),
+        Err(_) => ilst.remove_track_total(),
+    };
+    match tags.disk_number.parse::<u32>() {
+        Ok(n) => ilst.set_disk(n),
+        Err(_) => ilst.remove_disk(),
+    };
+    match tags.disk_total.parse::<u32>() {
+        Ok(n) => ilst.set_disk_total(n),
+        Err(_) => ilst.remove_disk_total(),
+    };
+    set_ilst_text(ilst, fourcc("aART"), tags.album_artist);
+    set_ilst_text(ilst, fourcc("©wrt"), tags.composer.join(sep));
+    set_ilst_text(ilst, fourcc("©lyr"), tags.lyrics);
+    set_ilst_text(ilst, fourcc("cprt"), tags.copyright);
+    set_ilst_text(ilst, fourcc("desc"), tags.description);
+    // The year is derived from ©day, same as ID3v2
+    match tags.date.is_empty() {
+        true => set_ilst_text(ilst, fourcc("©day"), tags.year),
+        false => set_ilst_text(ilst, fourcc("©day"), tags.date),
+    };
+    set_ilst_text(ilst, fourcc("©too"), tags.encoder);
+    let tmpo = fourcc("tmpo");
+    let _ = ilst.remove(&tmpo);
+    if let Ok(bpm) = tags.bpm.parse::<f64>() {
+        ilst.insert(Atom::new(tmpo, AtomData::SignedInteger(bpm.round() as i32)));
+    };
+    // rtng follows the ITUNESADVISORY values, 1 explicit and 2 clean
+    match tags.explicit.as_str() {
+        "1" => ilst.set_advisory_rating(AdvisoryRating::Explicit),
+        "2" => ilst.set_advisory_rating(AdvisoryRating::Clean),
+        "0" => ilst.set_advisory_rating(AdvisoryRating::Inoffensive),
+        _ => {
+            let _ = ilst.remove(&fourcc("rtng"));
+        }
+    };
+    set_ilst_text(ilst, itunes("PERFORMER"), tags.performer.join(sep));
+    set_ilst_text(ilst, itunes("PRODUCER"), tags.producer.join(sep));
+    set_ilst_text(ilst, itunes("ORIGINALDATE"), tags.original_date);
+    set_ilst_text(ilst, itunes("LABEL"), tags.label.join(sep));
+    set_ilst_text(ilst, itunes("ORGANIZATION"), tags.organization.join(sep));
+    set_ilst_text(ilst, itunes("BARCODE"), tags.barcode);
+    set_ilst_text(ilst, itunes("ISRC"), tags.isrc);
+    set_ilst_text(ilst, itunes("REPLAYGAIN_ALBUM_GAIN"), tags.replaygain_album_gain);
+    set_ilst_text(ilst, itunes("REPLAYGAIN_ALBUM_PEAK"), tags.replaygain_album_peak);
+    set_ilst_text(ilst, itunes("REPLAYGAIN_TRACK_GAIN"), tags.replaygain_track_gain);
+    set_ilst_text(ilst, itunes("REPLAYGAIN_TRACK_PEAK"), tags.replaygain_track_peak);
+    set_ilst_text(ilst, itunes("SOURCEID"), tags.source_id);
+    // extra_tags holds every other text atom of the file, atoms missing from it were removed or renamed
+    let current_extra_tags = DzrsTrackObjectTags::from_ilst(ilst, conf).extra_tags;
+    for item in current_extra_tags.iter() {
+        if conf.tag_clear_extra_tags || !tags.extra_tags.iter().any(|t| t.0 == item.0) {
+            let ident = ilst_extra_ident(ilst, &item.0);
+            let _ = ilst.remove(&ident);
+        };
+    }
+    if !conf.tag_clear_extra_tags {
+        // Repeated keys are joined into a single atom, mapped atoms would overwrite the fields above
+        let mut written: Vec<&str> = Vec::new();
+        for item in tags.extra_tags.iter() {
+            let ident = ilst_extra_ident(ilst, &item.0);
+            if !written.contains(&item.0.as_str()) && !is_ilst_mapped(&ident) {
+                let values: Vec<&str> = extra_tag_values(&tags.extra_tags, &item.0);
+                set_ilst_text(ilst, ident, values.join(sep));
+                written.push(&item.0);
+            };
+        }
+    }
+}
+
+// Replaces a text atom, empty values remove the atom
+fn set_ilst_text(ilst: &mut Ilst, ident: AtomIdent<'static>, value: String) {
+    let _ = ilst.remove(&ident);
+    if !value.is_empty() {
+        ilst.insert(Atom::new(ident, AtomData::UTF8(value)));
+    };
+}
+
+// Joins every text value of an atom, returns None when the atom is missing
+fn get_ilst_text(ilst: &Ilst, ident: &AtomIdent<'_>, sep: &str) -> Option<String> {
+    ilst.get(ident).map(|atom| get_ilst_values(atom).join(sep))
+}
+
+// Every text value of an atom, multiple values are kept when written by other taggers
+fn get_ilst_values(atom: &Atom<'_>) -> Vec<String> {
+    atom.data()
+        .filter_map(|d| match d {
+            AtomData::UTF8(v) | AtomData::UTF16(v) => Some(v.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+// Fourcc identifiers are latin-1, so "©nam" maps to [0xA9, b'n', b'a', b'm']
+fn fourcc(key: &str) -> AtomIdent<'static> {
+    let mut ident = [0u8; 4];
+    for (i, c) in key.chars().take(4).enumerate() {
+        ident[i] = c as u32 as u8;
+    }
+    AtomIdent::Fourcc(ident)
+}
+
+fn itunes(name: &str) -> AtomIdent<'static> {
+    AtomIdent::Freeform {
+        mean: Cow::Borrowed("com.apple.iTunes"),
+        name: Cow::Owned(name.to_string()),
+    }
+}
+
+// Extra tags of freeform atoms are keyed as "----:mean:name", so they are written back under the same mean
+const ILST_FREEFORM_PREFIX: &str = "----:";
+
+// Extra tags are keyed by the fourcc of their atom, or see ILST_FREEFORM_PREFIX for freeform atoms
+fn ilst_extra_key(ident: &AtomIdent<'_>) -> String {
+    match ident {
+        AtomIdent::Fourcc(f) => f.iter().map(|b| *b as char).collect(),
+        AtomIdent::Freeform { mean, name } => format!("{}{}:{}", ILST_FREEFORM_PREFIX, mean, name),
+    }
+}
+
+// Atom of an extra tag key, the reverse of ilst_extra_key, plain keys only stand for a fourcc already found
+// in the ilst, other plain keys, e.g. added by the user, are written as com.apple.iTunes freeform atoms
+fn ilst_extra_ident(ilst: &Ilst, key: &str) -> AtomIdent<'static> {
+    if let Some((mean, name)) = key.strip_prefix(ILST_FREEFORM_PREFIX).and_then(|k| k.split_once(':')) {
+        return AtomIdent::Freeform {
+            mean: Cow::Owned(mean.to_string()),
+            name: Cow::Owned(name.to_string()),
+        };
+    };
+    let is_fourcc = key.chars().count() == 4 && key.chars().all(|c| (c as u32) < 256);
+    match is_fourcc && ilst.get(&fourcc(key)).is_some() {
+        true => fourcc(key),
+        false => itunes(key),
+    }
+}
+
+// Atoms holding a DzrsTrackObjectTags field, see ILST_ATOMS and ILST_ITUNES_ATOMS
+fn is_ilst_mapped(ident: &AtomIdent<'_>) -> bool {
+    match ident {
+        AtomIdent::Fourcc(_) => ILST_ATOMS.contains(&ilst_extra_key(ident).as_str()),
+        AtomIdent::Freeform { mean, name } => {
+            *mean == "com.apple.iTunes" && ILST_ITUNES_ATOMS.contains(&name.to_uppercase().as_str())
+        }
+    }
+}
+
+// Parses lrc formatted lyrics ("[mm:ss.xx] line") into (milliseconds, line) pairs
+// returns None when the lyrics are not timestamped
+pub fn parse_lrc(lyrics: &str) -> Option<Vec<(u32, String)>> {
+    let re = Regex::new(r"^\[(\d+):(\d{2})(?:[\.:](\d{1,3}))?\]\s?(.*)$").unwrap();
+    let mut lines = Vec::new();
+    for line in lyrics.lines() {
+        if let Some(c) = re.captures(line.trim()) {
+            let minutes: u32 = c[1].parse().unwrap_or_default();
+            let seconds: u32 = c[2].parse().unwrap_or_default();
+            let fraction = c.get(3).map(|f| f.as_str()).unwrap_or("0");
+            let millis: u32 = format!("{:0<3}", fraction).parse().unwrap_or_default();
+            lines.push(((minutes * 60 + seconds) * 1000 + millis, c[4].to_string()));
+        };
+    }
+    match lines.is_empty() {
+        true => None,
+        false => Some(lines),
+    }
+}
+
+// Formats (milliseconds, line) pairs back into lrc lyrics, matching what apply_deezer produces
+pub fn format_lrc(lines: &[(u32, String)]) -> String {
+    lines
+        .iter()
+        .map(|(ms, line)| {
+            format!(
+                "[{:02}:{:02}.{:02}] {}",
+                ms / 60000,
+                (ms / 1000) % 60,
+                (ms % 1000) / 10,
+                line
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\r\n")
+}
+
+// Every value stored for an extra tag key
+fn extra_tag_values<'a>(extra_tags: &'a [(String, String)], key: &str) -> Vec<&'a str> {
+    extra_tags.iter().filter(|t| t.0 == key).map(|t| t.1.as_str()).collect()
+}
+
+// Extra tag keys follow the Vorbis field name rules (printable ascii without '='), which every format can store
+// keys are uppercased like the ones read from vorbis comments
+// MP4 freeform keys ("----:mean:name") keep their case, as atoms are matched exactly
+// keys of the ID3v2 frames and MP4 atoms holding a standard field are rejected as well, as writing them as extra tags
+// would overwrite that field
+pub fn validate_extra_tag_key(key: &str) -> Result<String, String> {
+    let key = match key.trim().strip_prefix(ILST_FREEFORM_PREFIX) {
+        Some(ident) => match ident.split_once(':') {
+            Some((mean, name)) if !mean.is_empty() && !name.is_empty() => key.trim().to_string(),
+            _ => {
+                return Err(format!(
+                    "Invalid freeform tag name {}, expected ----:mean:name",
+                    key.trim()
+                ))
+            }
+        },
+        None => key.trim().to_uppercase(),
+    };
+    if key.is_empty() {
+        return Err("Tag name cannot be empty".into());
+    };
+    if !key.chars().all(|c| (' '..='}').contains(&c) && c != '=') {
+        return Err(format!("Invalid tag name {}", key));
+    };
+    let is_mapped = MAPPED_TAG_KEYS.contains(&key.as_str())
+        || is_id3v2_mapped(&key)
+        || ILST_ATOMS.iter().any(|atom| atom.to_uppercase() == key)
+        || is_ilst_mapped(&ilst_extra_ident(&Ilst::default(), &key));
+    if is_mapped {
+        return Err(format!("Tag {} is already available as a standard tag", key));
+    };
+    Ok(key)
+}
+
+pub fn unique_indices(vec: &mut Vec<(usize, String)>) {
+    let mut index_map: HashMap<usize, usize> = HashMap::new();
+
+    for i in 0..vec.len() {
+        let (current_index, _) = &mut vec[i];
+        if let Some(new_index) = index_map.get_mut(current_index) {
+            *new_index += 1;
+            *current_index = *new_index;
+        } else {
+            index_map.insert(*current_index, *current_index);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DeezerTagger {
+    client: Deezer,
+    // Shared by every clone of the tagger
+    cache: Arc<DzrsResponseCache>,
+    limiter: Arc<DzrsRateLimiter>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct DeezerStructuredPayload {
+    pub track: Option<deezer_api::MainTrack>,
+    pub gw_track: Option<deezer_gw::Song>,
+    pub album: Option<deezer_api::MainAlbum>,
+    pub gw_album: Option<deezer_gw::Album>,
+    pub lyrics: Option<deezer_gw::Lyrics>,
+    pub cover: Option<Vec<u8>>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DzrsTrackObjectTags {
+    pub title: String,
+    pub artist: Vec<String>,
+    pub album: String,
+    pub album_artist: String,
+    pub composer: Vec<String>,
+    pub performer: Vec<String>,
+    pub producer: Vec<String>,
+    pub genre: Vec<String>,
+    pub length: String,
+    pub lyrics: String,
+    pub copyright: String,
+    pub description: String,
+    pub track_number: String,
+    pub track_total: String,
+    pub disk_number: String,
+    pub disk_total: String,
+    pub date: String,
+    pub year: String,
+    pub original_date: String,
+    pub comment: String,
+    pub label: Vec<String>,
+    pub organization: Vec<String>,
+    pub barcode: String,
+    pub isrc: String,
+    pub bpm: String,
+    pub explicit: String,
+    pub replaygain_album_gain: String,
+    pub replaygain_album_peak: String,
+    pub replaygain_track_gain: String,
+    pub replaygain_track_peak: String,
+    pub source_id: String,
+    pub encoder: String,
+    // Picture embedded as FrontCover when saving, following tag_cover_policy, None leaves the pictures untouched
+    pub cover: Option<DzrsTrackObjectPicture>,
+    pub extra_tags: Vec<(String, String)>,
+}
+
+// Keys mapped to a DzrsTrackObjectTags field, these cannot be used for extra tags
+const MAPPED_TAG_KEYS: [&str; 39] = [
+    "TITLE",
+    "ARTIST",
+    "ALBUM",
+    "ALBUMARTIST",
+    "COMPOSER",
+    "PERFORMER",
+    "PRODUCER",
+    "GENRE",
+    "LYRICS",
+    "COPYRIGHT",
+    "DESCRIPTION",
+    "TRACKNUMBER",
+    "TRACKTOTAL",
+    "TOTALTRACKS",
+    "DISCNUMBER",
+    "DISCTOTAL",
+    "TOTALDISCS",
+    "DATE",
+    "YEAR",
+    "ORIGINALDATE",
+    "COMMENT",
+    "LABEL",
+    "ORGANIZATION",
+    "BARCODE",
+    "ISRC",
+    "BPM",
+    "ITUNESADVISORY",
+    "REPLAYGAIN_ALBUM_GAIN",
+    "REPLAYGAIN_ALBUM_PEAK",
+    "REPLAYGAIN_TRACK_GAIN",
+    "REPLAYGAIN_TRACK_PEAK",
+    "SOURCEID",
+    "ENCODER",
+    "METADATA_BLOCK_PICTURE",
+    "COVERART",
+    "TXXX",
+    "APIC",
+    "COMM",
+    "USLT",
+];
+
+// Edits over DzrsTrackObjectTags.extra_tags requested from the frontend
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum DzrsExtraTagEdit {
+    Add {
+        key: String,
+        value: String,
+    },
+    Remove {
+        key: String,
+    },
+    Rename {
+        key: String,
+        #[serde(rename = "newKey")]
+        new_key: String,
+    },
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DzrsTrackObjectPicture {
+    pub b64: String,
+    pub pic_type: String,
+    pub description: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DzrsTrackObjectTagSource {
+    pub id: u64,
+    title: String,
+    link: String,
+    duration: u64,
+    artist: String,
+    album: String,
+    cover: String,
+    // How well the track matches the file from 0 to 100, see matching::match_score
+    pub score: u8,
+}
+
+const DEEZER_API_URL: &str = "https://api.deezer.com";
+
+// Lookups not covered by the deezer client, only the fields needed are deserialized
+#[derive(Deserialize)]
+struct DeezerId {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct DeezerAlbumTracks {
+    data: Vec<DeezerAlbumTrack>,
+}
+
+#[derive(Deserialize)]
+struct DeezerAlbumTrack {
+    id: u64,
+    title: String,
+    duration: u64,
+    track_position: u32,
+    disk_number: u32,
+    artist: DeezerArtist,
+}
+
+#[derive(Deserialize)]
+struct DeezerArtist {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct DeezerAlbumSearch {
+    data: Vec<DeezerAlbumResult>,
+}
+
+#[derive(Deserialize)]
+struct DeezerAlbumResult {
+    id: u64,
+    title: String,
+    nb_tracks: u32,
+    artist: DeezerArtist,
+}
+
+// Search results fetched in full when scoring sources, so their track and disk numbers and isrc are compared as well
+const MATCH_REFINE_COUNT: usize = 3;
+
+impl DeezerTagger {
+    // Responses are only cached in memory, see with_cache
+    pub fn new() -> Self {
+        let client = Deezer::new();
+        Self {
+            client,
+            cache: Arc::new(DzrsResponseCache::new(None)),
+            limiter: Arc::new(DzrsRateLimiter::default()),
+        }
+    }
+
+    // Responses are cached in memory and into dir, following cache_enabled and cache_ttl_hours
+    pub fn with_cache<P: AsRef<Path>>(dir: P, conf: &DzrsConfigurationParsed) -> Self {
+        let tagger = Self {
+            client: Deezer::new(),
+            cache: Arc::new(DzrsResponseCache::new(Some(dir.as_ref().to_path_buf()))),
+            limiter: Arc::new(DzrsRateLimiter::default()),
+        };
+        tagger.configure(conf);
+        tagger
+    }
+
+    // Applies the cache and request settings, shared by every clone of the tagger
+    pub fn configure(&self, conf: &DzrsConfigurationParsed) {
+        self.cache.configure(conf.cache_enabled, conf.cache_ttl_hours);
+        self.limiter
+            .configure(conf.request_rate_limit, conf.request_max_retries);
+    }
+
+    pub fn clear_cache(&self) -> Result<(), String> {
+        self.cache.clear()
+    }
+
+    // Sends a request to deezer within the rate limit, quota, busy and network errors are retried waiting longer
+    // each time, errors report how many retries were made
+    async fn request<T, Fut>(&self, fetch: impl Fn() -> Fut) -> Result<T, String>
+    where
+        Fut: Future<Output = Result<T, String>>,
+    {
+        let max_retries = self.limiter.max_retries();
+        let mut retries = 0;
+        loop {
+            self.limiter.acquire().await;
+            match fetch().await {
+                Ok(value) => return Ok(value),
+                Err(err) if retries < max_retries && is_retryable(&err) => {
+                    retries += 1;
+                    tokio::time::sleep(retry_delay(retries)).await;
+                }
+                Err(err) if retries > 0 => return Err(format!("{} (after {} retries)", err, retries)),
+                Err(err) => return Err(err),
+            };
+        }
+    }
+
+    // Response of an endpoint for an id, only fetched when not cached within the ttl, when fetching fails
+    // the expired response is served instead so tracks fetched before are still tagged offline
+    async fn cached<T, Fut>(&self, endpoint: &str, id: &str, fetch: impl Fn() -> Fut) -> Result<T, String>
+    where
+        T: Serialize + DeserializeOwned,
+        Fut: Future<Output = Result<T, String>>,
+    {
+        if let Some(value) = self.cache.get(endpoint, id) {
+            return Ok(value);
+        };
+        match self.request(fetch).await {
+            Ok(value) => {
+                self.cache.insert(endpoint, id, &value);
+                Ok(value)
+            }
+            Err(err) => self.cache.get_expired(endpoint, id).ok_or(err),
+        }
+    }
+
+    async fn track(&self, track_id: u64) -> Result<deezer_api::MainTrack, String> {
+        self.cached("track", &track_id.to_string(), || async {
+            self.client.track(track_id).await.map_err(|err| format!("{:?}", err))
+        })
+        .await
+    }
+
+    // Call deezer and get a structured payload back based on the given track id
+    // the album cover is only downloaded when a cover_size is given
+    pub async fn fetch_by_id(&self, track_id: u64, cover_size: Option<u32>) -> DeezerStructuredPayload {
+        let id = track_id.to_string();
+        let fut_track = self.track(track_id);
+        let fut_gw_track = self.cached("gw_song", &id, || async {
+            self.client.gw_song(track_id).await.map_err(|err| format!("{:?}", err))
+        });
+        let fut_lyrics = self.cached("gw_lyrics", &id, || async {
+            self.client
+                .gw_lyrics(track_id)
+                .await
+                .map_err(|err| format!("{:?}", err))
+        });
+
+        let track = fut_track.await.ok();
+        let album_id = track.as_ref().map(|tr| tr.album.id);
+
+        let (mut album, mut gw_album) = (None, None);
+        if let Some(a_id) = album_id {
+            let album_id = a_id.to_string();
+            let fut_album = self.cached("album", &album_id, || async {
+                self.client.album(a_id).await.map_err(|err| format!("{:?}", err))
+            });
+            let fut_gw_album = self.cached("gw_album", &album_id, || async {
+                self.client.gw_album(a_id).await.map_err(|err| format!("{:?}", err))
+            });
+
+            let (_album, _gw_album) = futures::join!(fut_album, fut_gw_album);
+            (album, gw_album) = (_album.ok(), _gw_album.ok())
+        }
+        let (gw_track, lyrics) = futures::join!(fut_gw_track, fut_lyrics);
+        let (gw_track, lyrics) = (gw_track.ok(), lyrics.ok());
+        let mut cover = None;
+        // Covers are cached base64 encoded, once per album and size
+        if let (Some(size), Some(a), Some(a_id)) = (cover_size, album.as_ref(), album_id) {
+            let b64 = self
+                .cached("cover", &format!("{}-{}", a_id, size), || async {
+                    let data = self.fetch_cover(&a.cover_xl, size).await?;
+                    Ok(general_purpose::STANDARD.encode(data))
+                })
+                .await;
+            cover = b64.ok().and_then(|b64| general_purpose::STANDARD.decode(b64).ok());
+        }
+        DeezerStructuredPayload {
+            track,
+            gw_track,
+            album,
+            gw_album,
+            lyrics,
+            cover,
+        }
+    }
+
+    // Finds the exact deezer track of a file from the tags identifying it, trying in order its SOURCEID, its ISRC
+    // and its BARCODE along with the track and disk numbers
+    pub async fn fetch_exact(&self, tags: &DzrsTrackObjectTags) -> Option<u64> {
+        if let Ok(id) = tags.source_id.trim().parse::<u64>() {
+            if let Ok(t) = self.track(id).await {
+                return Some(t.id);
+            };
+        };
+        let isrc: String = tags.isrc.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+        if !isrc.is_empty() {
+            if let Ok(t) = self
+                .get_api::<DeezerId>(&format!("track/isrc:{}", isrc.to_uppercase()), &[])
+                .await
+            {
+                return Some(t.id);
+            };
+        };
+        let position = leading_number(&tags.track_number)?;
+        let disk = leading_number(&tags.disk_number).unwrap_or(1);
+        for upc in barcode_variants(&tags.barcode) {
+            let album = match self.get_api::<DeezerId>(&format!("album/upc:{}", upc), &[]).await {
+                Ok(album) => album,
+                Err(_) => continue,
+            };
+            let tracks = self.fetch_album_tracks(album.id).await.ok()?;
+            return tracks
+                .into_iter()
+                .find(|(_, t)| {
+                    leading_number(&t.track_number) == Some(position) && leading_number(&t.disk_number) == Some(disk)
+                })
+                .map(|(id, _)| id);
+        }
+        None
+    }
+
+    // Call deezer and get the albums matching the query, errors on request fail
+    pub async fn fetch_album_sources(&self, query: &str) -> Result<Vec<DzrsAlbumSource>, String> {
+        let res = self
+            .get_api::<DeezerAlbumSearch>("search/album", &[("q", query)])
+            .await?;
+        Ok(res
+            .data
+            .into_iter()
+            .map(|a| DzrsAlbumSource {
+                id: a.id,
+                title: a.title,
+                artist: a.artist.name,
+                track_count: a.nb_tracks,
+            })
+            .collect())
+    }
+
+    // Tracklist of an album along with the track ids, in album order
+    pub async fn fetch_album_tracks(&self, album_id: u64) -> Result<Vec<(u64, DzrsMatchCandidate)>, String> {
+        let res = self
+            .get_api::<DeezerAlbumTracks>(&format!("album/{}/tracks", album_id), &[("limit", "500")])
+            .await?;
+        Ok(res
+            .data
+            .into_iter()
+            .map(|t| {
+                let candidate = DzrsMatchCandidate {
+                    title: t.title,
+                    artist: t.artist.name,
+                    duration: t.duration,
+                    track_number: t.track_position.to_string(),
+                    disk_number: t.disk_number.to_string(),
+                    ..Default::default()
+                };
+                (t.id, candidate)
+            })
+            .collect())
+    }
+
+    // Calls the public api directly, deezer answers unknown ids with an error object instead of an error status
+    // responses are cached as they are, keyed by path and query
+    async fn get_api<T: DeserializeOwned>(&self, path: &str, params: &[(&str, &str)]) -> Result<T, String> {
+        let url = reqwest::Url::parse_with_params(&format!("{}/{}", DEEZER_API_URL, path), params)
+            .map_err(|err| err.to_string())?;
+        let id = match url.query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path.to_string(),
+        };
+        let value: serde_json::Value = self
+            .cached("api", &id, || async {
+                let res = reqwest::get(url.clone())
+                    .await
+                    .and_then(|res| res.error_for_status())
+                    .map_err(|err| err.to_string())?;
+                let text = res.text().await.map_err(|err| err.to_string())?;
+                let value: serde_json::Value = serde_json::from_str(&text).map_err(|err| err.to_string())?;
+                if let Some(err) = value.get("error") {
+                    return Err(format!("Deezer error on {}, {}", path, err));
+                };
+                Ok(value)
+            })
+            .await?;
+        serde_json::from_value(value).map_err(|err| err.to_string())
+    }
+
+    // Download an album cover, deezer serves any square size by changing the dimensions within the url
+    pub async fn fetch_cover(&self, url: &str, size: u32) -> Result<Vec<u8>, String> {
+        let re = Regex::new(r"/\d+x\d+-").unwrap();
+        let url = re.replace(url, format!("/{}x{}-", size, size).as_str());
+        let res = reqwest::get(url.as_ref())
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|err| err.to_string())?;
+        let bytes = res.bytes().await.map_err(|err| err.to_string())?;
+        Ok(bytes.to_vec())
+    }
+
+    // Call deezer and get all possible matching tracks based on the given track metadata, each scored against the tags
+    // and duration in seconds of the file and sorted best first, errors on request fail
+    pub async fn fetch_sources(
+        &self,
+        query: &str,
+        tags: &DzrsTrackObjectTags,
+        duration: u64,
+    ) -> Result<Vec<DzrsTrackObjectTagSource>, String> {
+        let res = self
+            .request(|| async {
+                self.client
+                    .search(query, true)
+                    .await
+                    .map_err(|err| format!("{:?}", err))
+            })
+            .await?;
+
+        // Build the possible sources payload
+        let mut sources: Vec<DzrsTrackObjectTagSource> = res
+            .iter()
+            .map(|t| {
+                let mut source = DzrsTrackObjectTagSource::new(t);
+                source.score = match_score(tags, duration, &DzrsMatchCandidate::from(t));
+                source
+            })
+            .collect();
+        sources.sort_by(|a, b| b.score.cmp(&a.score));
+
+        // Rescore the best ones with their full track
+        let refined =
+            futures::future::join_all(sources.iter().take(MATCH_REFINE_COUNT).map(|s| self.track(s.id))).await;
+        for (source, track) in sources.iter_mut().zip(refined) {
+            if let Ok(t) = track {
+                source.score = match_score(tags, duration, &DzrsMatchCandidate::from(&t));
+            };
+        }
+        sources.sort_by(|a, b| b.score.cmp(&a.score));
+        Ok(sources)
+    }
+}
+
+// Barcodes are stored either as 12 digits UPC or 13 digits EAN, deezer only knows one of the two forms
+fn barcode_variants(barcode: &str) -> Vec<String> {
+    let digits: String = barcode.chars().filter(|c| c.is_ascii_digit()).collect();
+    match digits.len() {
+        12 => vec![digits.clone(), format!("0{}", digits)],
+        13 if digits.starts_with('0') => vec![digits.clone(), digits[1..].to_string()],
+        0 => Vec::new(),
+        _ => vec![digits],
+    }
+}
+
+impl DzrsTrackObjectTags {
+    // Maps a VorbisComments to a DzrsTrackObjectTags, repeated fields are kept as separate values
+    pub fn new(vorbis: &VorbisComments) -> Self {
+        let mut t = Self::default();
+        let mut extra_tags: Vec<(String, String)> = Vec::new();
+        let v = vorbis.items();
+        for tag in v {
+            match tag.0.to_uppercase().as_str() {
+                "TITLE" => t.title = tag.1.to_string(),
+                "ARTIST" => t.artist.push(tag.1.to_string()),
+                "BARCODE" => t.barcode = tag.1.to_string(),
+                "ALBUM" => t.album = tag.1.to_string(),
+                "ALBUMARTIST" => t.album_artist = tag.1.to_string(),
+                "COMMENT" => t.comment = tag.1.to_string(),
+                "COMPOSER" => t.composer.push(tag.1.to_string()),
+                "PERFORMER" => t.performer.push(tag.1.to_string()),
+                "PRODUCER" => t.producer.push(tag.1.to_string()),
+                "DESCRIPTION" => t.description = tag.1.to_string(),
+                "GENRE" => t.genre.push(tag.1.to_string()),
+                "LYRICS" => t.lyrics = tag.1.to_string(),
+                "COPYRIGHT" => t.copyright = tag.1.to_string(),
+                "TRACKNUMBER" => t.track_number = tag.1.to_string(),
+                "TRACKTOTAL" => t.track_total = tag.1.to_string(),
+                "TOTALTRACKS" => t.track_total = tag.1.to_string(),
+                "DISCNUMBER" => t.disk_number = tag.1.to_string(),
+                "DISCTOTAL" => t.disk_total = tag.1.to_string(),
+                "TOTALDISCS" => t.disk_total = tag.1.to_string(),
+                "DATE" => t.date = tag.1.to_string(),
+                "ORIGINALDATE" => t.original_date = tag.1.to_string(),
+                "LABEL" => t.label.push(tag.1.to_string()),
+                "ORGANIZATION" => t.organization.push(tag.1.to_string()),
+                "YEAR" => t.year = tag.1.to_string(),
+                "ISRC" => t.isrc = tag.1.to_string(),
+                "BPM" => t.bpm = tag.1.to_string(),
+                "ITUNESADVISORY" => t.explicit = tag.1.to_string(),
+                "REPLAYGAIN_ALBUM_GAIN" => t.replaygain_album_gain = tag.1.to_string(),
+                "REPLAYGAIN_ALBUM_PEAK" => t.replaygain_album_peak = tag.1.to_string(),
+                "REPLAYGAIN_TRACK_GAIN" => t.replaygain_track_gain = tag.1.to_string(),
+                "REPLAYGAIN_TRACK_PEAK" => t.replaygain_track_peak = tag.1.to_string(),
+                "SOURCEID" => t.source_id = tag.1.to_string(),
+                "ENCODER" => t.encoder = tag.1.to_string(),
+                // Pictures are read separately, legacy COVERART fields are ignored as well
+                "METADATA_BLOCK_PICTURE" | "COVERART" => (),
+                _ => extra_tags.push((tag.0.to_uppercase(), tag.1.to_string())),
+            };
+        }
+        t.extra_tags = extra_tags;
+        t
+    }
+
+    // Maps an Id3v2Tag to a DzrsTrackObjectTags, ID3v2.3 frames are already upgraded to ID3v2.4 when read
+    pub fn from_id3v2(id3: &Id3v2Tag, config: &DzrsConfigurationParsed) -> Self {
+        let sep = &config.tag_separator;
+        let mut t = Self::default();
+        // Multi valued text frames are null separated in ID3v2.4
+        let text = |id: &str| {
+            id3.get_text(id)
+                .unwrap_or_default()
+                .split('\0')
+                .collect::<Vec<&str>>()
+                .join(sep)
+        };
+        let values = |id: &str| split_id3v2_values(id3.get_text(id).unwrap_or_default());
+        let user_text = |description: &str| {
+            get_id3v2_mapped_user_text(id3, description)
+                .unwrap_or_default()
+                .to_string()
+        };
+        let user_values =
+            |description: &str| split_id3v2_values(get_id3v2_mapped_user_text(id3, description).unwrap_or_default());
+        let mut extra_tags: Vec<(String, String)> = Vec::new();
+        let mut sync_lyrics = String::new();
+        for frame in id3.iter() {
+            match frame.content() {
+                FrameValue::UnsynchronizedText(f) if frame.id_str() == "USLT" => t.lyrics = f.content.clone(),
+                FrameValue::Binary(data) if frame.id_str() == "SYLT" => {
+                    if let Ok(sylt) = SynchronizedText::parse(data) {
+                        sync_lyrics = format_lrc(&sylt.content);
+                    };
+                }
+                // TXXX descriptions keep their case, since they are matched exactly when written back
+                FrameValue::UserText(f) => {
+                    if !ID3V2_USER_TEXT_FRAMES.contains(&f.description.to_uppercase().as_str()) {
+                        f.content
+                            .split('\0')
+                            .for_each(|v| extra_tags.push((f.description.clone(), v.to_string())));
+                    };
+                }
+                FrameValue::Text(f) if !ID3V2_TEXT_FRAMES.contains(&frame.id_str()) => {
+                    f.value
+                        .split('\0')
+                        .for_each(|v| extra_tags.push((frame.id_str().to_string(), v.to_string())));
+                }
+                _ => (),
+            };
+        }
+        if t.lyrics.is_empty() {
+            t.lyrics = sync_lyrics;
+        };
+        t.title = text("TIT2");
+        t.artist = values("TPE1");
+        t.album = text("TALB");
+        t.album_artist = text("TPE2");
+        t.composer = values("TCOM");
+        t.genre = values("TCON");
+        t.copyright = text("TCOP");
+        (t.track_number, t.track_total) = split_id3v2_pair(id3.get_text("TRCK").unwrap_or_default());
+        (t.disk_number, t.disk_total) = split_id3v2_pair(id3.get_text("TPOS").unwrap_or_default());
+        t.date = text("TDRC");
+        t.year = t.date.get(..4).unwrap_or_default().to_string();
+        t.original_date = text("TDOR");
+        t.comment = id3.comment().map(|c| c.to_string()).unwrap_or_default();
+        t.label = values("TPUB");
+        t.isrc = text("TSRC");
+        t.bpm = text("TBPM");
+        t.encoder = text("TSSE");
+        t.performer = user_values("PERFORMER");
+        t.producer = user_values("PRODUCER");
+        t.description = user_text("DESCRIPTION");
+        t.organization = user_values("ORGANIZATION");
+        t.barcode = user_text("BARCODE");
+        t.explicit = user_text("ITUNESADVISORY");
+        t.replaygain_album_gain = user_text("REPLAYGAIN_ALBUM_GAIN");
+        t.replaygain_album_peak = user_text("REPLAYGAIN_ALBUM_PEAK");
+        t.replaygain_track_gain = user_text("REPLAYGAIN_TRACK_GAIN");
+        t.replaygain_track_peak = user_text("REPLAYGAIN_TRACK_PEAK");
+        t.source_id = user_text("SOURCEID");
+        t.extra_tags = extra_tags;
+        t
+    }
+
+    // Maps an Ilst to a DzrsTrackObjectTags
+    pub fn from_ilst(ilst: &Ilst, config: &DzrsConfigurationParsed) -> Self {
+        let sep = &config.tag_separator;
+        let mut t = Self::default();
+        let text = |key: &str| get_ilst_text(ilst, &fourcc(key), sep).unwrap_or_default();
+        let itunes_text = |name: &str| get_ilst_text(ilst, &itunes(name), sep).unwrap_or_default();
+        let values = |ident: AtomIdent<'_>| match ilst.get(&ident) {
+            Some(atom) => get_ilst_values(atom),
+            None => Vec::new(),
+        };
+        let mut extra_tags: Vec<(String, String)> = Vec::new();
+        for atom in ilst.iter() {
+            if is_ilst_mapped(atom.ident()) {
+                continue;
+            };
+            // Freeform means and names keep their case, since they are matched exactly when written back
+            let key = ilst_extra_key(atom.ident());
+            if let Some(value) = get_ilst_text(ilst, atom.ident(), sep) {
+                if !value.is_empty() {
+                    extra_tags.push((key, value));
+                };
+            };
+        }
+        t.title = text("©nam");
+        t.artist = values(fourcc("©ART"));
+        t.album = text("©alb");
+        t.album_artist = text("aART");
+        t.composer = values(fourcc("©wrt"));
+        t.genre = values(fourcc("©gen"));
+        t.comment = text("©cmt");
+        t.lyrics = text("©lyr");
+        t.copyright = text("cprt");
+        t.description = text("desc");
+        t.date = text("©day");
+        t.year = t.date.get(..4).unwrap_or_default().to_string();
+        t.encoder = text("©too");
+        t.track_number = ilst.track().map(|n| n.to_string()).unwrap_or_default();
+        t.track_total = ilst.track_total().map(|n| n.to_string()).unwrap_or_default();
+        t.disk_number = ilst.disk().map(|n| n.to_string()).unwrap_or_default();
+        t.disk_total = ilst.disk_total().map(|n| n.to_string()).unwrap_or_default();
+        if let Some(atom) = ilst.get(&fourcc("tmpo")) {
+            t.bpm = match atom.data().next() {
+                Some(AtomData::SignedInteger(i)) => i.to_string(),
+                Some(AtomData::UnsignedInteger(i)) => i.to_string(),
+                _ => String::new(),
+            };
+        };
+        t.explicit = match ilst.advisory_rating() {
+            Some(AdvisoryRating::Explicit) => "1".into(),
+            Some(AdvisoryRating::Clean) => "2".into(),
+            Some(AdvisoryRating::Inoffensive) => "0".into(),
+            None => String::new(),
+        };
+        t.performer = values(itunes("PERFORMER"));
+        t.producer = values(itunes("PRODUCER"));
+        t.original_date = itunes_text("ORIGINALDATE");
+        t.label = values(itunes("LABEL"));
+        t.organization = values(itunes("ORGANIZATION"));
+        t.barcode = itunes_text("BARCODE");
+        t.isrc = itunes_text("ISRC");
+        t.replaygain_album_gain = itunes_text("REPLAYGAIN_ALBUM_GAIN");
+        t.replaygain_album_peak = itunes_text("REPLAYGAIN_ALBUM_PEAK");
+        t.replaygain_track_gain = itunes_text("REPLAYGAIN_TRACK_GAIN");
+        t.replaygain_track_peak = itunes_text("REPLAYGAIN_TRACK_PEAK");
+        t.source_id = itunes_text("SOURCEID");
+        t.extra_tags = extra_tags;
+        t
+    }
+
+    // Applies an edit over extra_tags, the file is only changed once these tags are saved
+    pub fn edit_extra_tag(&mut self, edit: DzrsExtraTagEdit) -> Result<(), String> {
+        match edit {
+            DzrsExtraTagEdit::Add { key, value } => {
+                let key = validate_extra_tag_key(&key)?;
+                self.extra_tags.push((key, value));
+            }
+            DzrsExtraTagEdit::Remove { key } => {
+                if !self.extra_tags.iter().any(|t| t.0 == key) {
+                    return Err(format!("Tag {} not found", key));
+                };
+                self.extra_tags.retain(|t| t.0 != key);
+            }
+            DzrsExtraTagEdit::Rename { key, new_key } => {
+                let new_key = validate_extra_tag_key(&new_key)?;
+                if !self.extra_tags.iter().any(|t| t.0 == key) {
+                    return Err(format!("Tag {} not found", key));
+                };
+                if key != new_key && self.extra_tags.iter().any(|t| t.0 == new_key) {
+                    return Err(format!("Tag {} already exists", new_key));
+                };
+                self.extra_tags
+                    .iter_mut()
+                    .filter(|t| t.0 == key)
+                    .for_each(|t| t.0 = new_key.clone());
+            }
+        };
+        Ok(())
+    }
+
+    // Applies values retrieved from deezer, only updating fields based on config
+    pub fn apply_deezer(&mut self, payload: DeezerStructuredPayload, conf: &DzrsConfigurationParsed) {
+        let mut artists: Vec<String> = vec![];
+
+        if let Some(t) = payload.track {
+            if conf.tag_dz_title {
+                if conf.tag_remove_feat_title {
+                    // Removing (feat. ArtistName) from title
+                    let re = Regex::new(r"\(feat\.?\s*[^\)]*\)").unwrap();
+                    let _t = t.title.clone();
+                    let new_t = re.replace_all(&_t, "").to_string();
+                    self.title = new_t;
+                } else {
+                    self.title = t.title;
+                }
+            };
+            if conf.tag_dz_album {
+                self.album = t.album.title;
+            };
+            if conf.tag_dz_album_artist {
+                self.album_artist = t.artist.name.clone();
+            };
+            artists.push(t.artist.name);
+            if conf.tag_dz_track_number {
+                if conf.tag_pad_track {
+                    self.track_number = format!("{:0>2}", t.track_position);
+                } else {
+                    self.track_number = t.track_position.to_string()
+                };
+            };
+            if conf.tag_dz_disk_number {
+                if conf.tag_pad_disk {
+                    self.disk_number = format!("{:0>2}", t.disk_number);
+                } else {
+                    self.disk_number = t.disk_number.to_string()
+                };
+            };
+            if conf.tag_dz_date {
+                if conf.tag_date_as_year {
+                    self.date = t.release_date[..=3].to_string();
+                } else {
+                    self.date = t.release_date.clone();
+                };
+            };
+            if conf.tag_dz_year {
+                self.year = t.release_date[..=3].to_string();
+            }
+            if conf.tag_dz_isrc {
+                self.isrc = t.isrc;
+            };
+            if conf.tag_dz_bpm {
+                if t.bpm != 0.0 {
+                    self.bpm = t.bpm.to_string();
+                };
+            };
+            if conf.tag_dz_explicit {
+                self.explicit = match t.explicit_lyrics {
+                    true => "1",
+                    false => "0",
+                }
+                .into();
+            };
+            if conf.tag_dz_replaygain_track_gain {
+                if t.gain != 0.0 {
+                    self.replaygain_track_gain = t.gain.to_string() + " dB";
+                };
+            };
+            if conf.tag_dz_source_id {
+                self.source_id = t.id.to_string();
+            };
+        };
+
+        if let Some(t) = payload.gw_track {
+            let mut _artists = t.artists.clone();
+            _artists.sort_by(|a, b| {
+                a.artists_songs_order
+                    .parse::<u16>()
+                    .unwrap_or_default()
+                    .cmp(&b.artists_songs_order.parse::<u16>().unwrap_or_default())
+            });
+            _artists.iter().for_each(|a| {
+                if !artists.contains(&a.art_name) {
+                    artists.push(a.art_name.clone())
+                };
+            });
+            let contributors = match t.sng_contributors {
+                deezer_gw::SngContributors::SngContributors(contributors) => contributors,
+                deezer_gw::SngContributors::Empty(_) => HashMap::new(),
+            };
+            if conf.tag_dz_composer {
+                if let Some(composers) = contributors.get("composer") {
+                    self.composer = composers.clone()
+                };
+            };
+            if conf.tag_dz_performer {
+                if let Some(performer) = contributors.get("performer") {
+                    self.performer = performer.clone()
+                };
+            };
+            if conf.tag_dz_producer {
+                if let Some(producer) = contributors.get("producer") {
+                    self.producer = producer.clone()
+                };
+            };
+        };
+
+        if let Some(a) = payload.album {
+            if conf.tag_dz_genre {
+                let mut split_genres: Vec<(usize, String)> = Vec::new();
+                let mut genres: Vec<String> = a
+                    .genres
+                    .data
+                    .clone()
+                    .into_iter()
+                    .filter(|g| !g.name.contains("/"))
+                    .map(|g| g.name)
+                    .collect();
+
+                a.genres
+                    .data
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_, g)| g.name.contains("/"))
+                    .for_each(|(i, g)| {
+                        let genre_names: Vec<&str> = g.name.split('/').collect();
+                        for genre_name in genre_names {
+                            split_genres.push((i, genre_name.trim().to_string()));
+                        }
+                    });
+
+                unique_indices(&mut split_genres);
+
+                for (i, g) in split_genres {
+                    if !genres.contains(&g) {
+                        genres.insert(i, g);
+                    };
+                }
+
+                self.genre = genres;
+            };
+            if conf.tag_dz_label {
+                self.label = vec![a.label.clone()];
+            }
+            if conf.tag_dz_organization {
+                self.organization = vec![a.label]
+            }
+            if conf.tag_dz_barcode {
+                self.barcode = a.upc;
+            }
+        };
+
+        if let Some(a) = payload.gw_album {
+            if conf.tag_dz_copyright {
+                self.copyright = a.copyright;
+            };
+            if conf.tag_dz_track_total {
+                if conf.tag_pad_track_total {
+                    self.track_total = format!("{:0>2}", a.number_track);
+                } else {
+                    self.track_total = a.number_track;
+                };
+            };
+            if conf.tag_dz_disk_total {
+                if conf.tag_pad_disk_total {
+                    self.disk_total = format!("{:0>2}", a.number_disk);
+                } else {
+                    self.disk_total = a.number_disk;
+                };
+            };
+            if conf.tag_dz_original_date {
+                if let Some(d) = a.original_release_date {
+                    if conf.tag_originaldate_as_year {
+                        self.original_date = d[..=3].to_string();
+                    } else {
+                        self.original_date = d;
+                    };
+                };
+            };
+        };
+
+        if let Some(l) = payload.lyrics {
+            if conf.tag_dz_lyrics {
+                if conf.tag_prefer_sync_lyrics && l.lyrics_sync_json.is_some() {
+                    let mut lines = Vec::new();
+                    for l_line in l.lyrics_sync_json.unwrap().into_iter() {
+                        let mut line = String::new();
+                        if let Some(mut t) = l_line.lrc_timestamp {
+                            t.push(' ');
+                            line.push_str(t.as_str());
+                        };
+                        line.push_str(l_line.line.as_str());
+                        lines.push(line);
+                    }
+                    self.lyrics = lines.join("\r\n");
+                } else {
+                    self.lyrics = l.lyrics_text
+                };
+            };
+        };
+
+        if conf.tag_dz_artist {
+            self.artist = artists;
+        };
+
+        if let Some(c) = payload.cover {
+            if conf.tag_dz_cover {
+                self.cover = DzrsTrackObjectPicture::from_bytes(c).ok();
+            };
+        };
+    }
+}
+
+impl DzrsTrackObjectPicture {
+    pub fn new(value: &(Picture, PictureInformation)) -> Self {
+        let data = value.0.data();
+        let b64 = general_purpose::STANDARD.encode(data);
+        let pic_type = format!("{:?}", value.0.pic_type());
+        let description = value.0.description().unwrap_or_default().to_string();
+        Self {
+            b64,
+            pic_type,
+            description,
+            width: value.1.width,
+            height: value.1.height,
+        }
+    }
+
+    // Create a FrontCover picture from raw image data, the mime type is detected from the data
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, String> {
+        let mut picture = Picture::from_reader(&mut Cursor::new(data)).map_err(|err| err.to_string())?;
+        picture.set_pic_type(PictureType::CoverFront);
+        let info = PictureInformation::from_picture(&picture).map_err(|err| err.to_string())?;
+        Ok(Self::new(&(picture, info)))
+    }
+
+    // Decode this picture back into a FrontCover lofty Picture, used when embedding it into a file
+    pub fn to_front_cover(&self) -> Result<Picture, String> {
+        let data = general_purpose::STANDARD
+            .decode(&self.b64)
+            .map_err(|err| err.to_string())?;
+        let mut picture = Picture::from_reader(&mut Cursor::new(data)).map_err(|err| err.to_string())?;
+        picture.set_pic_type(PictureType::CoverFront);
+        Ok(picture)
+    }
+}
+
+impl DzrsTrackObjectTagSource {
+    pub fn new(value: &deezer_api::Track) -> Self {
+        let value = value.to_owned();
+        Self {
+            id: value.id,
+            title: value.title,
+            link: value.link.unwrap_or_default(),
+            duration: value.duration,
+            artist: value.artist.name,
+            album: value.album.title,
+            cover: value.album.cover,
+            score: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn barcode_variants_upc_adds_ean() {
+        assert_eq!(barcode_variants("724384260910"), vec!["724384260910", "0724384260910"]);
+    }
+
+    #[test]
+    fn barcode_variants_ean_with_leading_zero_adds_upc() {
+        assert_eq!(barcode_variants("0724384260910"), vec!["0724384260910", "724384260910"]);
+    }
+
+    #[test]
+    fn barcode_variants_keeps_other_barcodes() {
+        assert_eq!(barcode_variants("5099902894225"), vec!["5099902894225"]);
+        assert_eq!(barcode_variants("12345"), vec!["12345"]);
+    }
+
+    #[test]
+    fn barcode_variants_ignores_separators() {
+        assert_eq!(
+            barcode_variants(" 7 24384 26091 0 "),
+            vec!["724384260910", "0724384260910"]
+        );
+        assert_eq!(
+            barcode_variants("0-7243-8426-0910"),
+            vec!["0724384260910", "724384260910"]
+        );
+    }
+
+    #[test]
+    fn barcode_variants_empty() {
+        assert!(barcode_variants("").is_empty());
+        assert!(barcode_variants("n/a").is_empty());
+    }
+
+    #[test]
+    fn is_id3v2_frame_id_known_frames() {
+        assert!(is_id3v2_frame_id("TKEY"));
+        assert!(is_id3v2_frame_id("TCMP"));
+    }
+
+    #[test]
+    fn is_id3v2_frame_id_rejects_other_keys() {
+        // Looks like a text frame but is not one, so it is written as TXXX
+        assert!(!is_id3v2_frame_id("TEST"));
+        assert!(!is_id3v2_frame_id("TXXX"));
+        assert!(!is_id3v2_frame_id("APIC"));
+        assert!(!is_id3v2_frame_id("tkey"));
+        assert!(!is_id3v2_frame_id("MOOD"));
+    }
+
+    #[test]
+    fn from_id3v2_reads_mapped_user_text_ignoring_case() {
+        let mut id3 = Id3v2Tag::default();
+        id3.insert_user_text("Barcode".to_string(), "724384260910".to_string());
+        id3.insert_user_text("Performer".to_string(), "First\0Second".to_string());
+        id3.insert_user_text("Mood".to_string(), "calm".to_string());
+        let tags = DzrsTrackObjectTags::from_id3v2(&id3, &DzrsConfigurationParsed::default());
+        assert_eq!(tags.barcode, "724384260910");
+        assert_eq!(tags.performer, vec!["First", "Second"]);
+        assert_eq!(tags.extra_tags, vec![("Mood".to_string(), "calm".to_string())]);
+    }
+
+    #[test]
+    fn set_id3v2_tags_replaces_mapped_user_text_ignoring_case() {
+        let mut id3 = Id3v2Tag::default();
+        id3.insert_user_text("Barcode".to_string(), "724384260910".to_string());
+        let conf = DzrsConfigurationParsed::default();
+        let mut tags = DzrsTrackObjectTags::from_id3v2(&id3, &conf);
+        tags.barcode = "0724384260910".to_string();
+        set_id3v2_tags(&tags, &mut id3, &conf);
+        let barcodes: Vec<&str> = id3
+            .iter()
+            .filter_map(|frame| match frame.content() {
+                FrameValue::UserText(f) if f.description.eq_ignore_ascii_case("BARCODE") => Some(f.content.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(barcodes, vec!["0724384260910"]);
+    }
+
+    #[test]
+    fn validate_extra_tag_key_rejects_mapped_frames() {
+        for key in ["TIT2", "tpe1", "TALB", "TRCK", "TPOS", "TSRC", "APIC"] {
+            assert!(validate_extra_tag_key(key).is_err(), "{}", key);
+        }
+        assert!(validate_extra_tag_key("Barcode").is_err());
+    }
+
+    #[test]
+    fn validate_extra_tag_key_rejects_mapped_atoms() {
+        assert!(validate_extra_tag_key("trkn").is_err());
+        assert!(validate_extra_tag_key("----:com.apple.iTunes:ISRC").is_err());
+        assert!(validate_extra_tag_key("----:com.apple.iTunes:Isrc").is_err());
+        assert_eq!(
+            validate_extra_tag_key("----:com.example:ISRC"),
+            Ok("----:com.example:ISRC".to_string())
+        );
+    }
+
+    #[test]
+    fn validate_extra_tag_key_accepts_other_frames() {
+        assert_eq!(validate_extra_tag_key(" tkey "), Ok("TKEY".to_string()));
+        assert_eq!(validate_extra_tag_key("mood"), Ok("MOOD".to_string()));
+    }
+
+    fn ilst_with(ident: AtomIdent<'static>) -> Ilst {
+        let mut ilst = Ilst::default();
+        ilst.insert(Atom::new(ident, AtomData::UTF8("value".to_string())));
+        ilst
+    }
+
+    #[test]
+    fn ilst_extra_key_round_trips_fourcc() {
+        let ident = AtomIdent::Fourcc([0xA9, b'w', b'r', b'k']);
+        let ilst = ilst_with(ident.clone());
+        let key = ilst_extra_key(&ident);
+        assert_eq!(key, "\u{a9}wrk");
+        assert_eq!(ilst_extra_ident(&ilst, &key), ident);
+    }
+
+    #[test]
+    fn ilst_extra_key_round_trips_freeform() {
+        let ident = AtomIdent::Freeform {
+            mean: Cow::Borrowed("com.example"),
+            name: Cow::Borrowed("Mood"),
+        };
+        let ilst = ilst_with(ident.clone());
+        let key = ilst_extra_key(&ident);
+        assert_eq!(key, "----:com.example:Mood");
+        assert_eq!(ilst_extra_ident(&ilst, &key), ident);
+        assert_eq!(validate_extra_tag_key(&key), Ok(key));
+    }
+
+    #[test]
+    fn ilst_extra_ident_unknown_key_is_itunes_freeform() {
+        // A 4 character key only stands for a fourcc already in the ilst
+        assert_eq!(ilst_extra_ident(&Ilst::default(), "MOOD"), itunes("MOOD"));
+        assert_eq!(
+            ilst_extra_ident(&Ilst::default(), "CATALOGNUMBER"),
+            itunes("CATALOGNUMBER")
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use crate::types::tags::DzrsTrackObjectTags;
+
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+// Characters not allowed within file names on at least one of the supported platforms
+const UNSAFE_FILE_NAME_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+const MAX_FILE_NAME_LEN: usize = 200;
+// Fields only matching digits when parsing file names, so "01. Title" splits as expected
+const NUMERIC_FIELDS: [&str; 8] = [
+    "tracknumber",
+    "track",
+    "tracktotal",
+    "discnumber",
+    "disc",
+    "disctotal",
+    "year",
+    "bpm",
+];
+
+// Renders a file template over the given tags, the syntax is:
+// %field%      replaced with the value of field, e.g. %title% or %albumartist%
+// %field:N%    numeric values are zero padded to N digits, e.g. %tracknumber:2%
+// [ ... ]      the section is dropped when any field within it is empty, e.g. [%discnumber%-]%tracknumber:2%
+// %%           a literal percent sign
+// Field values are sanitized, so they can never introduce path separators or invalid characters
+pub fn render_template(template: &str, tags: &DzrsTrackObjectTags, sep: &str) -> Result<String, String> {
+    // Each open section keeps its rendered text and whether one of its fields was empty
+    let mut sections: Vec<(String, bool)> = vec![(String::new(), false)];
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => sections.push((String::new(), false)),
+            ']' => {
+                if sections.len() == 1 {
+                    return Err(format!("Unexpected ']' in template {}", template));
+                };
+                let (text, missing) = sections.pop().unwrap();
+                let parent = sections.last_mut().unwrap();
+                if !missing {
+                    parent.0.push_str(&text);
+                };
+            }
+            '%' => {
+                let mut token = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '%' {
+                        closed = true;
+                        break;
+                    };
+                    token.push(c);
+                }
+                if !closed {
+                    return Err(format!("Unclosed '%' in template {}", template));
+                };
+                let section = sections.last_mut().unwrap();
+                if token.is_empty() {
+                    section.0.push('%');
+                    continue;
+                };
+                let value = render_field(&token, tags, sep)?;
+                if value.is_empty() {
+                    section.1 = true;
+                };
+                section.0.push_str(&replace_unsafe_chars(&value));
+            }
+            _ => sections.last_mut().unwrap().0.push(c),
+        };
+    }
+    if sections.len() > 1 {
+        return Err(format!("Unclosed '[' in template {}", template));
+    };
+    Ok(sections.pop().unwrap().0)
+}
+
+// Renders a file name from the template, keeping the given extension
+pub fn render_file_name(
+    template: &str,
+    tags: &DzrsTrackObjectTags,
+    sep: &str,
+    extension: &str,
+) -> Result<String, String> {
+    let name = sanitize_file_name(&render_template(template, tags, sep)?);
+    if name.is_empty() {
+        return Err(format!("Template {} renders an empty file name", template));
+    };
+    match extension.is_empty() {
+        true => Ok(name),
+        false => Ok(format!("{}.{}", name, extension)),
+    }
+}
+
+// Renders a relative path from a directory template, each '/' separated part becomes a folder and the last one
+// the file name, parts rendering empty (e.g. a [%discnumber%] section without disc) are skipped
+pub fn render_path(template: &str, tags: &DzrsTrackObjectTags, sep: &str, extension: &str) -> Result<PathBuf, String> {
+    let parts: Vec<&str> = template.split(['/', '\\']).collect();
+    let (file_part, dir_parts) = match parts.split_last() {
+        Some(split) => split,
+        None => return Err(format!("Invalid directory template {}", template)),
+    };
+    let mut path = PathBuf::new();
+    for part in dir_parts {
+        let dir = sanitize_file_name(&render_template(part, tags, sep)?);
+        if !dir.is_empty() {
+            path.push(dir);
+        };
+    }
+    path.push(render_file_name(file_part, tags, sep, extension)?);
+    Ok(path)
+}
+
+// Replaces characters not allowed within file names, collapses whitespace and trims trailing dots
+// which windows silently strips, the result is also capped to a safe length
+pub fn sanitize_file_name(name: &str) -> String {
+    let mut name = replace_unsafe_chars(name)
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ");
+    if name.len() > MAX_FILE_NAME_LEN {
+        let mut end = MAX_FILE_NAME_LEN;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name.truncate(end);
+    };
+    name.trim_end_matches(|c: char| c == '.' || c.is_whitespace())
+        .to_string()
+}
+
+fn replace_unsafe_chars(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match UNSAFE_FILE_NAME_CHARS.contains(&c) || c.is_control() {
+            true => '_',
+            false => c,
+        })
+        .collect()
+}
+
+fn render_field(token: &str, tags: &DzrsTrackObjectTags, sep: &str) -> Result<String, String> {
+    let (name, pad) = match token.split_once(':') {
+        Some((name, pad)) => {
+            let pad: usize = pad.parse().map_err(|_| format!("Invalid padding in %{}%", token))?;
+            (name, pad)
+        }
+        None => (token, 0),
+    };
+    let value = template_field(name, tags, sep).ok_or_else(|| format!("Unknown template field %{}%", name))?;
+    let value = value.trim();
+    match !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()) {
+        true => Ok(format!("{:0>width$}", value, width = pad)),
+        false => Ok(value.to_string()),
+    }
+}
+
+// Template field names follow the vorbis field names, lowercased
+fn template_field(name: &str, tags: &DzrsTrackObjectTags, sep: &str) -> Option<String> {
+    let value = match name.to_lowercase().as_str() {
+        "title" => tags.title.clone(),
+        "artist" => tags.artist.join(sep),
+        "album" => tags.album.clone(),
+        "albumartist" => tags.album_artist.clone(),
+        "composer" => tags.composer.join(sep),
+        "performer" => tags.performer.join(sep),
+        "producer" => tags.producer.join(sep),
+        "genre" => tags.genre.join(sep),
+        "tracknumber" | "track" => tags.track_number.clone(),
+        "tracktotal" => tags.track_total.clone(),
+        "discnumber" | "disc" => tags.disk_number.clone(),
+        "disctotal" => tags.disk_total.clone(),
+        "date" => tags.date.clone(),
+        "year" => tags.year.clone(),
+        "originaldate" => tags.original_date.clone(),
+        "label" => tags.label.join(sep),
+        "organization" => tags.organization.join(sep),
+        "barcode" => tags.barcode.clone(),
+        "isrc" => tags.isrc.clone(),
+        "bpm" => tags.bpm.clone(),
+        "sourceid" => tags.source_id.clone(),
+        _ => return None,
+    };
+    Some(value)
+}
+
+// Fills tags by matching a file path against a pattern using the template %field% syntax
+// the last '/' separated part is matched against the file name without extension, the others against its parent folders
+// e.g. "%artist% - %album%/%tracknumber%. %title%", tags are only changed when the whole pattern matches
+pub fn parse_file_path(pattern: &str, path: &Path, tags: &mut DzrsTrackObjectTags, sep: &str) -> Result<(), String> {
+    let mut segments: Vec<String> = vec![path.file_stem().unwrap_or_default().to_string_lossy().to_string()];
+    let mut dir = path.parent();
+    while let Some(d) = dir {
+        if let Some(name) = d.file_name() {
+            segments.push(name.to_string_lossy().to_string());
+        };
+        dir = d.parent();
+    }
+    let parts: Vec<&str> = pattern.split(['/', '\\']).collect();
+    if parts.len() > segments.len() {
+        return Err(format!(
+            "{} has less folders than the pattern {}",
+            path.display(),
+            pattern
+        ));
+    };
+    let mut values: Vec<(String, String)> = Vec::new();
+    for (part, segment) in parts.iter().rev().zip(segments.iter()) {
+        let (re, fields) = pattern_regex(part)?;
+        let caps = re
+            .captures(segment)
+            .ok_or_else(|| format!("{} does not match the pattern {}", segment, part))?;
+        for (i, field) in fields.into_iter().enumerate() {
+            if let Some(m) = caps.get(i + 1) {
+                values.push((field, m.as_str().to_string()));
+            };
+        }
+    }
+    // Fields are set on a copy, so an unknown field leaves the tags untouched as well
+    let mut parsed = tags.clone();
+    for (field, value) in values {
+        set_template_field(&field, &mut parsed, &value, sep)?;
+    }
+    *tags = parsed;
+    Ok(())
+}
+
+// Builds an anchored regex out of a single pattern part, returning the fields in capture order
+fn pattern_regex(part: &str) -> Result<(Regex, Vec<String>), String> {
+    let mut re = String::from("^");
+    let mut fields = Vec::new();
+    let mut chars = part.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            re.push_str(&regex::escape(&c.to_string()));
+            continue;
+        };
+        let mut token = String::new();
+        let mut closed = false;
+        for c in chars.by_ref() {
+            if c == '%' {
+                closed = true;
+                break;
+            };
+            token.push(c);
+        }
+        if !closed {
+            return Err(format!("Unclosed '%' in pattern {}", part));
+        };
+        if token.is_empty() {
+            re.push('%');
+            continue;
+        };
+        let name = token.to_lowercase();
+        match NUMERIC_FIELDS.contains(&name.as_str()) {
+            true => re.push_str(r"(\d+)"),
+            false => re.push_str("(.+?)"),
+        };
+        fields.push(name);
+    }
+    re.push('$');
+    let re = Regex::new(&re).map_err(|err| err.to_string())?;
+    Ok((re, fields))
+}
+
+// Inverse of template_field, multi valued fields are split with the separator
+fn set_template_field(name: &str, tags: &mut DzrsTrackObjectTags, value: &str, sep: &str) -> Result<(), String> {
+    let value = value.trim();
+    let values: Vec<String> = match sep.trim().is_empty() {
+        true => vec![value.to_string()],
+        false => value
+            .split(sep.trim())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .collect(),
+    };
+    let value = value.to_string();
+    match name {
+        "title" => tags.title = value,
+        "artist" => tags.artist = values,
+        "album" => tags.album = value,
+        "albumartist" => tags.album_artist = value,
+        "composer" => tags.composer = values,
+        "performer" => tags.performer = values,
+        "producer" => tags.producer = values,
+        "genre" => tags.genre = values,
+        "tracknumber" | "track" => tags.track_number = value,
+        "tracktotal" => tags.track_total = value,
+        "discnumber" | "disc" => tags.disk_number = value,
+        "disctotal" => tags.disk_total = value,
+        "date" => tags.date = value,
+        "year" => tags.year = value,
+        "originaldate" => tags.original_date = value,
+        "label" => tags.label = values,
+        "organization" => tags.organization = values,
+        "barcode" => tags.barcode = value,
+        "isrc" => tags.isrc = value,
+        "bpm" => tags.bpm = value,
+        "sourceid" => tags.source_id = value,
+        _ => return Err(format!("Unknown template field %{}%", name)),
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags() -> DzrsTrackObjectTags {
+        DzrsTrackObjectTags {
+            title: "Song".to_string(),
+            artist: vec!["First".to_string(), "Second".to_string()],
+            track_number: "3".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn render_template_fields_and_padding() {
+        let rendered = render_template("%tracknumber:2% - %artist% - %title%", &tags(), ", ");
+        assert_eq!(rendered, Ok("03 - First, Second - Song".to_string()));
+        // Only numeric values are padded
+        assert_eq!(render_template("%title:5%", &tags(), ", "), Ok("Song".to_string()));
+    }
+
+    #[test]
+    fn render_template_drops_sections_with_empty_fields() {
+        let mut tags = tags();
+        assert_eq!(
+            render_template("[%discnumber%-]%tracknumber:2%", &tags, ", "),
+            Ok("03".to_string())
+        );
+        tags.disk_number = "1".to_string();
+        assert_eq!(
+            render_template("[%discnumber%-]%tracknumber:2%", &tags, ", "),
+            Ok("1-03".to_string())
+        );
+        assert_eq!(
+            render_template("[[%discnumber%]-%album%]x", &tags, ", "),
+            Ok("x".to_string())
+        );
+    }
+
+    #[test]
+    fn render_template_literal_percent() {
+        assert_eq!(
+            render_template("100%% %title%", &tags(), ", "),
+            Ok("100% Song".to_string())
+        );
+    }
+
+    #[test]
+    fn render_template_sanitizes_values() {
+        let mut tags = tags();
+        tags.title = "AC/DC: Live?".to_string();
+        assert_eq!(render_template("%title%", &tags, ", "), Ok("AC_DC_ Live_".to_string()));
+    }
+
+    #[test]
+    fn render_template_errors() {
+        assert!(render_template("%title", &tags(), ", ").is_err());
+        assert!(render_template("%title%]", &tags(), ", ").is_err());
+        assert!(render_template("[%title%", &tags(), ", ").is_err());
+        assert!(render_template("%unknown%", &tags(), ", ").is_err());
+        assert!(render_template("%tracknumber:x%", &tags(), ", ").is_err());
+    }
+
+    #[test]
+    fn sanitize_file_name_collapses_whitespace_and_trailing_dots() {
+        assert_eq!(sanitize_file_name("  a \t  b  "), "a _ b");
+        assert_eq!(sanitize_file_name("name. . ."), "name");
+        assert_eq!(sanitize_file_name("a<b>c"), "a_b_c");
+        assert_eq!(sanitize_file_name("..."), "");
+    }
+
+    #[test]
+    fn sanitize_file_name_truncates_on_char_boundary() {
+        let name = sanitize_file_name(&"a\u{e9}".repeat(100));
+        assert!(name.len() <= MAX_FILE_NAME_LEN);
+        assert!(name.len() >= MAX_FILE_NAME_LEN - 1);
+        assert!(name.ends_with('a'));
+    }
+
+    #[test]
+    fn parse_file_path_fills_tags_from_folders_and_file_name() {
+        let mut tags = DzrsTrackObjectTags::default();
+        let path = Path::new("/music/First; Second - Album/01. 02 Intro.flac");
+        parse_file_path("%artist% - %album%/%tracknumber%. %title%", path, &mut tags, "; ").unwrap();
+        assert_eq!(tags.artist, vec!["First", "Second"]);
+        assert_eq!(tags.album, "Album");
+        assert_eq!(tags.track_number, "01");
+        assert_eq!(tags.title, "02 Intro");
+    }
+
+    #[test]
+    fn parse_file_path_numeric_fields_only_match_digits() {
+        let mut tags = DzrsTrackObjectTags::default();
+        assert!(parse_file_path("%tracknumber% %title%", Path::new("A1 Song.mp3"), &mut tags, ", ").is_err());
+        parse_file_path("%tracknumber%%title%", Path::new("12Song.mp3"), &mut tags, ", ").unwrap();
+        assert_eq!((tags.track_number.as_str(), tags.title.as_str()), ("12", "Song"));
+    }
+
+    #[test]
+    fn parse_file_path_leaves_tags_untouched_on_mismatch() {
+        let mut tags = tags();
+        let path = Path::new("/music/Album/Song.flac");
+        assert!(parse_file_path("%album%/%tracknumber% - %title%", path, &mut tags, ", ").is_err());
+        assert_eq!(tags, self::tags());
+        assert!(parse_file_path("%artist%/%album%/%title%", Path::new("Song.flac"), &mut tags, ", ").is_err());
+        assert!(parse_file_path("%title", Path::new("Song.flac"), &mut tags, ", ").is_err());
+        assert!(parse_file_path("%unknown%", Path::new("Song.flac"), &mut tags, ", ").is_err());
+        assert!(parse_file_path("%artist% - %bogus%", Path::new("Other - Song.flac"), &mut tags, ", ").is_err());
+        assert_eq!(tags, self::tags());
+    }
+}
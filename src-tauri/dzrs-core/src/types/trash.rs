@@ -0,0 +1,246 @@
+use std::path::{Path, PathBuf};
+
+// Moves a file into the trash of the platform so it can be restored by the user
+// Linux and other unix systems follow the freedesktop.org trash specification, Windows and macOS use the system trash
+pub fn trash_file<P: AsRef<Path>>(path: P) -> Result<(), String> {
+    let path = absolute_path(path.as_ref()).map_err(|err| format!("{}: {}", path.as_ref().display(), err))?;
+    trash_file_platform(&path).map_err(|err| format!("Cannot move {} to the trash: {}", path.display(), err))
+}
+
+// Only the parent directory is resolved, so a symlink is trashed itself rather than the file it points to
+fn absolute_path(path: &Path) -> Result<PathBuf, String> {
+    let name = path.file_name().ok_or("Not a file")?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let path = parent.canonicalize().map_err(|err| err.to_string())?.join(name);
+    std::fs::symlink_metadata(&path).map_err(|err| err.to_string())?;
+    Ok(path)
+}
+
+// The recycle bin is reached through IFileOperation within the process, so no console window or dialog shows up
+#[cfg(target_os = "windows")]
+fn trash_file_platform(path: &Path) -> Result<(), String> {
+    trash::delete(path).map_err(|err| err.to_string())
+}
+
+#[cfg(target_os = "macos")]
+fn trash_file_platform(path: &Path) -> Result<(), String> {
+    let path = path.to_string_lossy().replace('\\', "\\\\").replace('"', "\\\"");
+    let script = format!("tell application \"Finder\" to delete POSIX file \"{}\"", path);
+    run_command("osascript", &["-e", &script])
+}
+
+#[cfg(target_os = "macos")]
+fn run_command(cmd: &str, args: &[&str]) -> Result<(), String> {
+    let output = std::process::Command::new(cmd)
+        .args(args)
+        .output()
+        .map_err(|err| err.to_string())?;
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+    }
+}
+
+// The home trash is used for files on the same device, files elsewhere go to the trash of their mount point
+// since moving them to the home trash would mean copying the whole file, see topdir_trash
+#[cfg(all(unix, not(target_os = "macos")))]
+fn trash_file_platform(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let file_dev = std::fs::symlink_metadata(path).map_err(|err| err.to_string())?.dev();
+    let home_trash = dirs_next::data_dir()
+        .map(|d| d.join("Trash"))
+        .ok_or("Cannot find the home trash directory")?;
+    let home_trash_dev = home_trash
+        .ancestors()
+        .find_map(|p| std::fs::metadata(p).ok())
+        .map(|m| m.dev());
+    let (files_dir, info_dir) = match home_trash_dev == Some(file_dev) {
+        true => create_trash_dirs(&home_trash)?,
+        false => topdir_trash(&mount_point(path, file_dev))?,
+    };
+
+    // The info file is created first to reserve the name, as required by the specification
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        url_encode(&path.to_string_lossy()),
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+    let (name, info_path) = reserve_name(path, &info_dir, &info)?;
+    if let Err(err) = std::fs::rename(path, files_dir.join(&name)) {
+        let _ = std::fs::remove_file(info_path);
+        return Err(err.to_string());
+    };
+    Ok(())
+}
+
+// $topdir/.Trash/$uid when the administrator provided a $topdir/.Trash directory with the sticky bit set, which must
+// not be a symlink, otherwise $topdir/.Trash-$uid
+#[cfg(all(unix, not(target_os = "macos")))]
+fn topdir_trash(topdir: &Path) -> Result<(PathBuf, PathBuf), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    // SAFETY: getuid cannot fail and has no preconditions
+    let uid = unsafe { libc::getuid() };
+    let shared = topdir.join(".Trash");
+    let shared_valid = std::fs::symlink_metadata(&shared)
+        .map(|m| m.is_dir() && m.permissions().mode() & 0o1000 != 0)
+        .unwrap_or(false);
+    if shared_valid {
+        if let Ok(dirs) = create_trash_dirs(&shared.join(uid.to_string())) {
+            return Ok(dirs);
+        };
+    };
+    create_trash_dirs(&topdir.join(format!(".Trash-{}", uid)))
+}
+
+// The files and info directories of a trash, only accessible by the user
+#[cfg(all(unix, not(target_os = "macos")))]
+fn create_trash_dirs(trash_dir: &Path) -> Result<(PathBuf, PathBuf), String> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    let files_dir = trash_dir.join("files");
+    let info_dir = trash_dir.join("info");
+    for dir in [&files_dir, &info_dir] {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .map_err(|err| err.to_string())?;
+    }
+    Ok((files_dir, info_dir))
+}
+
+// Topmost ancestor of the file still on the same device
+#[cfg(all(unix, not(target_os = "macos")))]
+fn mount_point(path: &Path, dev: u64) -> PathBuf {
+    use std::os::unix::fs::MetadataExt;
+
+    path.ancestors()
+        .skip(1)
+        .take_while(|p| std::fs::symlink_metadata(p).map(|m| m.dev() == dev).unwrap_or(false))
+        .last()
+        .unwrap_or(Path::new("/"))
+        .to_path_buf()
+}
+
+// Names already in the trash are suffixed with a counter, e.g. "song.2.flac"
+#[cfg(all(unix, not(target_os = "macos")))]
+fn reserve_name(path: &Path, info_dir: &Path, info: &str) -> Result<(String, PathBuf), String> {
+    use std::io::Write;
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    for i in 1..10000 {
+        let name = match i {
+            1 => format!("{}{}", stem, ext),
+            _ => format!("{}.{}{}", stem, i, ext),
+        };
+        let info_path = info_dir.join(format!("{}.trashinfo", name));
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(mut file) => {
+                file.write_all(info.as_bytes()).map_err(|err| err.to_string())?;
+                return Ok((name, info_path));
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.to_string()),
+        };
+    }
+    Err("Too many files with the same name in the trash".into())
+}
+
+// Percent encodes a path as the Path key of the info file expects, slashes are kept
+#[cfg(all(unix, not(target_os = "macos")))]
+fn url_encode(path: &str) -> String {
+    path.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(all(test, unix, not(target_os = "macos")))]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dzrs-trash-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn url_encode_keeps_slashes_and_safe_characters() {
+        assert_eq!(url_encode("/music/a-b_c.~1.flac"), "/music/a-b_c.~1.flac");
+        assert_eq!(url_encode("/music/Song \u{e9}#1.flac"), "/music/Song%20%C3%A9%231.flac");
+        assert_eq!(url_encode("100%"), "100%25");
+    }
+
+    #[test]
+    fn reserve_name_suffixes_taken_names() {
+        let dir = temp_dir("reserve");
+        let path = Path::new("/music/song.flac");
+        let (first, first_info) = reserve_name(path, &dir, "info").unwrap();
+        let (second, _) = reserve_name(path, &dir, "info").unwrap();
+        let (third, _) = reserve_name(path, &dir, "info").unwrap();
+        let (no_ext, _) = reserve_name(Path::new("/music/song"), &dir, "info").unwrap();
+        let info = std::fs::read_to_string(first_info).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            (first.as_str(), second.as_str(), third.as_str()),
+            ("song.flac", "song.2.flac", "song.3.flac")
+        );
+        assert_eq!(no_ext, "song");
+        assert_eq!(info, "info");
+    }
+
+    #[test]
+    fn absolute_path_keeps_symlinks() {
+        let dir = temp_dir("symlink");
+        let (target, link) = (dir.join("target.flac"), dir.join("link.flac"));
+        std::fs::write(&target, "").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let resolved = absolute_path(&dir.join(".").join("link.flac"));
+        let missing = absolute_path(&dir.join("missing.flac"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(resolved.unwrap().file_name().unwrap(), "link.flac");
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn topdir_trash_falls_back_without_a_shared_trash() {
+        let dir = temp_dir("topdir");
+        // Without the sticky bit the shared trash is not trusted
+        std::fs::create_dir(dir.join(".Trash")).unwrap();
+        let (files_dir, info_dir) = topdir_trash(&dir).unwrap();
+        let uid = unsafe { libc::getuid() };
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files_dir, dir.join(format!(".Trash-{}", uid)).join("files"));
+        assert_eq!(info_dir, dir.join(format!(".Trash-{}", uid)).join("info"));
+    }
+
+    #[test]
+    fn topdir_trash_uses_the_shared_trash() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("shared");
+        std::fs::create_dir(dir.join(".Trash")).unwrap();
+        std::fs::set_permissions(dir.join(".Trash"), std::fs::Permissions::from_mode(0o1777)).unwrap();
+        let (files_dir, _) = topdir_trash(&dir).unwrap();
+        let uid = unsafe { libc::getuid() };
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files_dir, dir.join(".Trash").join(uid.to_string()).join("files"));
+    }
+}
@@ -1,52 +1,29 @@
 // Prevents additional console window on Windows in release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod config;
-mod types;
-
-use crate::config::DzrsConfiguration;
-use crate::types::files::{self, DzrsTrackObject, DzrsTrackObjectTagState, DzrsTrackObjectWrapper};
-use crate::types::tags::{DeezerTagger, DzrsTrackObjectTags};
+use dzrs_core::config::{DzrsConfiguration, DzrsConfigurationParsed};
+use dzrs_core::platform_app_dir;
+use dzrs_core::types::album::fetch_albums_tags;
+use dzrs_core::types::audio::open_audio_file;
+use dzrs_core::types::diff::{diff_track, DzrsTrackObjectDiff};
+use dzrs_core::types::files::{self, DzrsTrackObject, DzrsTrackObjectTagState, DzrsTrackObjectWrapper};
+use dzrs_core::types::history::{new_batch_id, DzrsHistory, DzrsHistoryEntry};
+use dzrs_core::types::index::DzrsLibraryIndex;
+use dzrs_core::types::tags::{DeezerTagger, DzrsExtraTagEdit, DzrsTrackObjectPicture, DzrsTrackObjectTags};
+use dzrs_core::types::trash::trash_file;
 
 use notify::{recommended_watcher, RecommendedWatcher, RecursiveMode, Watcher};
-use regex::Regex;
-use std::env;
 use std::env::consts::OS;
-use std::ops::Deref;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use tauri::{Manager, State, Window};
-
-pub fn platform_app_dir() -> PathBuf {
-    let mut path = PathBuf::new();
-    match OS {
-        "linux" => {
-            if let Ok(xdg_data_home) = env::var("XDG_DATA_HOME") {
-                path.push(xdg_data_home);
-            } else if let Ok(home) = env::var("HOME") {
-                path.push(home);
-                path.push(".local/share");
-            }
-            path.push("Dzrs");
-        }
-        "macos" => {
-            if let Ok(home) = env::var("HOME") {
-                path.push(home);
-                path.push("Library/Application Support");
-                path.push("Dzrs");
-            }
-        }
-        "windows" => {
-            if let Ok(appdata) = env::var("APPDATA") {
-                path.push(appdata);
-                path.push("Dzrs");
-            }
-        }
-        _ => (),
-    }
-    path
-}
+use tauri::{AppHandle, Manager, RunEvent, State, Window};
+
+// Number of files per chunk streamed to the front-end while loading a directory
+const LOAD_CHUNK_SIZE: usize = 100;
+// Incremented by every tracks_get_dir call, chunks still loading for a previous directory are discarded
+static LOAD_GENERATION: AtomicUsize = AtomicUsize::new(0);
 
 pub fn browse<P: AsRef<Path>>(path: P) -> Result<(), String> {
     let path = path.as_ref().to_str().unwrap();
@@ -81,7 +58,7 @@ async fn tracks_replace(
     t.replace_track(&path)?;
     match t.get_track_obj_mut(&path) {
         Some(tr) => {
-            // Try loading tags, error is ignored for non-flac
+            // Try loading tags, error is ignored for unsupported files
             let _ = tr.load_tags(&conf);
         }
         None => (),
@@ -97,10 +74,14 @@ async fn tracks_insert(
 ) -> Result<(), String> {
     let conf = configuration.lock().unwrap().parsed();
     let mut t = tracks.lock().unwrap();
+    // The watcher reports every created file, including the ones within ignored directories
+    if t.is_ignored(&path, &conf) {
+        return Ok(());
+    };
     t.insert_track(&path)?;
     match t.get_track_obj_mut(&path) {
         Some(tr) => {
-            // Try loading tags, error is ignored for non-flac
+            // Try loading tags, error is ignored for unsupported files
             let _ = tr.load_tags(&conf);
         }
         None => (),
@@ -136,28 +117,98 @@ async fn tracks_get(
     }
 }
 
+// Scans the directory and returns its files right away, files unchanged since they were indexed come from the library index
+// the others have their tags loaded in parallel on the blocking thread pool and streamed to the front-end in chunks
+// through tracks_loaded events, tracks_loading_done is emitted once all are loaded
 #[tauri::command]
 async fn tracks_get_dir(
     dir: Option<String>,
+    app: AppHandle,
     tracks: State<'_, Mutex<DzrsTrackObjectWrapper>>,
+    index: State<'_, Mutex<DzrsLibraryIndex>>,
     config: State<'_, Mutex<DzrsConfiguration>>,
 ) -> Result<Vec<DzrsTrackObject>, String> {
     let conf = config.lock().unwrap().parsed();
-    let mut t = tracks.lock().unwrap();
     let dir = match dir {
         Some(p) => p,
         None => conf.directory_view_path.clone(),
     };
-    match DzrsTrackObjectWrapper::new(dir) {
-        Ok(mut tr) => {
-            tr.iter_mut().for_each(|track| {
-                // Try loading tags, error is ignored for non-flac
-                let _ = track.load_tags(&conf);
-            });
-            *t = tr.clone();
-            Ok(tr.items)
+    let generation = LOAD_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    // Keep the previously loaded directory before replacing it
+    let _ = persist_index(&app);
+    let conf_ = conf.clone();
+    let mut tr = tauri::async_runtime::spawn_blocking(move || DzrsTrackObjectWrapper::new(dir, &conf_))
+        .await
+        .map_err(|err| err.to_string())??;
+    index.lock().unwrap().restore(&mut tr);
+    *tracks.lock().unwrap() = tr.clone();
+
+    // Unsupported files are never indexed, so they are tried again as well
+    let pending: Vec<DzrsTrackObject> = tr.iter().filter(|track| track.file_type.is_empty()).cloned().collect();
+    let chunks: Arc<Vec<Vec<DzrsTrackObject>>> =
+        Arc::new(pending.chunks(LOAD_CHUNK_SIZE).map(|c| c.to_vec()).collect());
+    let next = Arc::new(AtomicUsize::new(0));
+    let workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+    let handles: Vec<_> = (0..workers.min(chunks.len()))
+        .map(|_| {
+            let (app, chunks, next, conf) = (app.clone(), chunks.clone(), next.clone(), conf.clone());
+            tauri::async_runtime::spawn_blocking(move || load_chunks(&app, &chunks, &next, generation, &conf))
+        })
+        .collect();
+    tauri::async_runtime::spawn(async move {
+        for handle in handles {
+            let _ = handle.await;
         }
-        Err(err) => Err(err),
+        if LOAD_GENERATION.load(Ordering::SeqCst) == generation {
+            let _ = persist_index(&app);
+            let _ = app.emit_all("tracks_loading_done", ());
+        };
+    });
+    Ok(tr.items)
+}
+
+// Stores the inner DzrsTrackObjects into the library index and writes it to disk
+fn persist_index(app: &AppHandle) -> Result<(), String> {
+    let tracks = app.state::<Mutex<DzrsTrackObjectWrapper>>();
+    let index = app.state::<Mutex<DzrsLibraryIndex>>();
+    let t = tracks.lock().unwrap();
+    let mut i = index.lock().unwrap();
+    i.update(&t.root, &t.items);
+    i.save()
+}
+
+// Worker loop of tracks_get_dir, takes the next chunk until none are left or a newer directory load started
+fn load_chunks(
+    app: &AppHandle,
+    chunks: &[Vec<DzrsTrackObject>],
+    next: &AtomicUsize,
+    generation: usize,
+    conf: &DzrsConfigurationParsed,
+) {
+    loop {
+        let mut chunk = match chunks.get(next.fetch_add(1, Ordering::SeqCst)) {
+            Some(chunk) => chunk.clone(),
+            None => break,
+        };
+        if LOAD_GENERATION.load(Ordering::SeqCst) != generation {
+            break;
+        };
+        chunk.iter_mut().for_each(|track| {
+            // Try loading tags, error is ignored for unsupported files
+            let _ = track.load_tags(conf);
+        });
+        {
+            let tracks = app.state::<Mutex<DzrsTrackObjectWrapper>>();
+            let mut t = tracks.lock().unwrap();
+            if LOAD_GENERATION.load(Ordering::SeqCst) != generation {
+                break;
+            };
+            for track in chunk.iter() {
+                // Files removed meanwhile by the watcher are skipped
+                let _ = t.replace_track_obj(track.clone());
+            }
+        }
+        let _ = app.emit_all("tracks_loaded", chunk);
     }
 }
 
@@ -174,12 +225,52 @@ async fn config_get(configuration: State<'_, Mutex<DzrsConfiguration>>) -> Resul
 }
 
 #[tauri::command]
-async fn config_set(key: String, value: String, config: State<'_, Mutex<DzrsConfiguration>>) -> Result<(), String> {
+async fn config_set(
+    key: String,
+    value: String,
+    config: State<'_, Mutex<DzrsConfiguration>>,
+    tagger: State<'_, DeezerTagger>,
+) -> Result<(), String> {
     let mut conf = config.lock().unwrap();
     conf.update(key, value);
+    tagger.configure(&conf.parsed());
     conf.save().map_err(|err| err.to_string())
 }
 
+// Drops every cached deezer response, both from memory and from disk
+#[tauri::command]
+async fn cache_clear(tagger: State<'_, DeezerTagger>) -> Result<(), String> {
+    tagger.clear_cache()
+}
+
+// Fills the tags_to_save of the inner DzrsTrackObjects by parsing their file path with the tag_filename_pattern
+// Errors with a vector of each file not matching the pattern
+#[tauri::command]
+async fn tracks_tags_from_filename(
+    paths: Vec<String>,
+    tracks: State<'_, Mutex<DzrsTrackObjectWrapper>>,
+    config: State<'_, Mutex<DzrsConfiguration>>,
+) -> Result<(), Vec<String>> {
+    let conf = config.lock().unwrap().parsed();
+    let mut t = tracks.lock().unwrap();
+    let mut errors = Vec::new();
+    for p in paths {
+        match t.get_track_obj_mut(&p) {
+            Some(tr) if !tr.file_type.is_empty() => {
+                if let Err(err) = tr.tags_from_filename(&conf) {
+                    errors.push(err);
+                };
+            }
+            _ => (),
+        };
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
 // Fetch tags from deezer and apply them into the inner DzrsTrackObjects for each loaded path
 // Errors with a vector of each request that failed
 #[tauri::command]
@@ -197,61 +288,15 @@ async fn tracks_fetch(
     // For each path fetch tags from deezer and create an owned updated version of DzrsTrackObject, stored into trs
     // to later update the inner DzrsTrackObjectWrapper
     // NOTE The mutex is immediately released and we work on a cloned version of DzrsTrackObjectWrapper, this allows us
-    // to call async methods, in this case fetch_by_query, and after the async calls have been made, the mutex gets locked again
+    // to call async methods, in this case fetch_sources, and after the async calls have been made, the mutex gets locked again
     // and the inner DzrsTrackObjectWrapper gets updated with the new values from deezer
     for p in paths {
-        match t.get_track_obj(&p) {
-            Some(tr) => {
-                // Fetch tags from deezer using the track metadata
-                let mut tr = tr.to_owned();
-                let _file_name = tr.file_name.to_owned();
-                let re_title = Regex::new(r"[\[\(].*?(?:with|feat).*?[\]\)]").unwrap();
-                let re_album = Regex::new(r"[\[\(]?(?i:explicit)[\]\)]?").unwrap();
-                // Stripping featured artists from the title and explicit from album, messes up the deezer search
-                // Also stripping '&' messes up the url
-                let _title = tr.tags.title.to_owned().replace("&", "");
-                let _album = tr.tags.album.to_owned().replace("&", "");
-                let _artist = tr.tags.artist.to_owned().replace("&", "");
-                let _title = re_title.replace_all(&_title, "");
-                let _album = re_album.replace_all(&_album, "");
-                let file_name = _file_name.strip_suffix(".flac").unwrap();
-                let tr_meta = (_title.deref(), _album.deref(), _artist.deref());
-
-                let queries = match (
-                    tr_meta.0.is_empty() && tr_meta.1.is_empty() && tr_meta.2.is_empty(),
-                    conf.tag_fetch_with_filename,
-                ) {
-                    (true, true) => (file_name.to_owned(), file_name.to_owned()),
-                    _ => (
-                        format!(r#"track:"{}" album:"{}" artist:"{}""#, tr_meta.0, tr_meta.1, tr_meta.2),
-                        format!(r#"track:"{}" album:"{}""#, tr_meta.0, tr_meta.1),
-                    ),
-                };
-                let payload = match tagger.fetch_by_query(&queries.0).await {
-                    Ok(p) => Ok(p),
-                    Err(_) => tagger.fetch_by_query(&queries.1).await,
-                };
-                // Update the DzrsTrackObject using the fetched tags
-                match payload {
-                    Ok(payload) => {
-                        tr.tags_deezer.apply_deezer(payload.0.clone(), &conf);
-                        tr.tags_to_save.apply_deezer(payload.0, &conf);
-                        match payload.1.len() {
-                            l if l > 1 => tr.tags_status = DzrsTrackObjectTagState::Successfull,
-                            1 => tr.tags_status = DzrsTrackObjectTagState::Matched,
-                            _ => (),
-                        }
-                        tr.tags_sources = payload.1;
-                        trs.push(tr);
-                    }
-                    Err(err) => {
-                        tr.tags_status = DzrsTrackObjectTagState::Unsuccessfull;
-                        trs.push(tr);
-                        errors.push(err);
-                    }
-                };
-            }
-            _ => (),
+        if let Some(tr) = t.get_track_obj(&p) {
+            let mut tr = tr.to_owned();
+            if let Err(err) = tr.fetch_tags(tagger.inner(), &conf).await {
+                errors.push(err);
+            };
+            trs.push(tr);
         };
     }
 
@@ -267,6 +312,35 @@ async fn tracks_fetch(
     }
 }
 
+// Fetch tags from deezer album by album, tracks of the same folder and album are tagged from a single deezer album
+// Errors with a vector of each file which could not be tagged
+#[tauri::command]
+async fn tracks_fetch_album(
+    paths: Vec<String>,
+    tracks: State<'_, Mutex<DzrsTrackObjectWrapper>>,
+    tagger: State<'_, DeezerTagger>,
+    config: State<'_, Mutex<DzrsConfiguration>>,
+) -> Result<(), Vec<String>> {
+    let mut trs: Vec<DzrsTrackObject> = {
+        let t = tracks.lock().unwrap();
+        paths.iter().filter_map(|p| t.get_track_obj(p).cloned()).collect()
+    };
+    let conf = config.lock().unwrap().parsed();
+    // As in tracks_fetch the mutex is released while calling deezer
+    let mut errors = fetch_albums_tags(&mut trs, tagger.inner(), &conf).await;
+
+    let mut t = tracks.lock().unwrap();
+    for tr in trs {
+        if let Err(err) = t.replace_track_obj(tr) {
+            errors.push(err);
+        };
+    }
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
 // Fetch possibile tracks matching the query (source) from deezer and apply them into the inner DzrsTrackObject
 #[tauri::command]
 async fn tracks_fetch_sources(
@@ -282,29 +356,8 @@ async fn tracks_fetch_sources(
         Some(tr) => {
             // Fetch sources from deezer using the track metadata
             let mut tr = tr.to_owned();
-            // Stripping '&' messes up the url
-            let _title = tr.tags.title.to_owned().replace("&", "");
-            let _album = tr.tags.album.to_owned().replace("&", "");
-            let _artist = tr.tags.artist.to_owned().replace("&", "");
-            let tr_meta = (_title.deref(), _album.deref(), _artist.deref());
-            let query = match (
-                tr_meta.0.is_empty() && tr_meta.1.is_empty() && tr_meta.2.is_empty(),
-                conf.tag_fetch_with_filename,
-            ) {
-                (true, true) => tr.file_name.clone().strip_suffix(".flac").unwrap().into(),
-                _ => format!(r#"track:"{}" album:"{}" artist:"{}""#, tr_meta.0, tr_meta.1, tr_meta.2),
-            };
-            let sources = tagger.fetch_sources(&query).await;
-            // Update the DzrsTrackObject with the fetched sources
-            match sources {
-                Ok(sources) => {
-                    tr.tags_sources = sources;
-                    tracks.lock().unwrap().replace_track_obj(tr).unwrap();
-                }
-                Err(err) => {
-                    return Err(err);
-                }
-            };
+            tr.fetch_sources(tagger.inner(), &conf).await?;
+            tracks.lock().unwrap().replace_track_obj(tr)?;
         }
         _ => (),
     };
@@ -327,7 +380,10 @@ async fn tracks_fetch_sources_manual(
         Some(tr) => {
             // Fetch sources from deezer using the track metadata
             let mut tr = tr.to_owned();
-            let sources = tagger.fetch_sources(&query).await;
+            let match_tags = tr.match_tags(&tr.tags);
+            let sources = tagger
+                .fetch_sources(&query, &match_tags, tr.audio_properties.duration)
+                .await;
             // Update the DzrsTrackObject with the fetched sources
             match sources {
                 Ok(sources) => {
@@ -362,10 +418,7 @@ async fn tracks_source(
     match t.get_track_obj_mut(&path) {
         Some(tr) => {
             let mut tr = tr.to_owned();
-            let payload = tagger.fetch_by_id(id).await;
-            tr.tags_deezer.apply_deezer(payload.clone(), &conf);
-            tr.tags_to_save.apply_deezer(payload, &conf);
-            tr.tags_status = DzrsTrackObjectTagState::Matched;
+            tr.fetch_source(tagger.inner(), id, &conf).await;
             tracks.lock().unwrap().replace_track_obj(tr)?;
         }
         _ => (),
@@ -395,38 +448,58 @@ async fn tracks_reload(
     Ok(())
 }
 
+// Dry run of save_tags, returns the field level diff between the file and the given tags without writing anything
+#[tauri::command]
+async fn tracks_diff(
+    path: String,
+    tags: DzrsTrackObjectTags,
+    tracks: State<'_, Mutex<DzrsTrackObjectWrapper>>,
+    config: State<'_, Mutex<DzrsConfiguration>>,
+) -> Result<DzrsTrackObjectDiff, String> {
+    let conf = config.lock().unwrap().parsed();
+    let tr = match tracks.lock().unwrap().get_track_obj(&path) {
+        Some(tr) => tr.clone(),
+        None => return Err(format!("Cannot find inner DzrsTrackObject for {}", path)),
+    };
+    diff_track(&tr, &tags, &conf)
+}
+
 // Saves given tags into a file and updates the inner DzrsTrackObject to match the saved file
+// with history_enabled the file is backed up first, saves sharing the same batch can be reverted together
 #[tauri::command]
 async fn save_tags(
     path: String,
     tags: DzrsTrackObjectTags,
+    batch: Option<String>,
     tracks: State<'_, Mutex<DzrsTrackObjectWrapper>>,
+    history: State<'_, Mutex<DzrsHistory>>,
     config: State<'_, Mutex<DzrsConfiguration>>,
 ) -> Result<(), String> {
     let conf = config.lock().unwrap().parsed();
-    let mut errors: Vec<String> = Vec::new();
-    // Save tags into the flac
-    files::save_tags(&path, &tags, &conf)?;
-    // Update the loaded track to match the saved file
-    let mut track_ = DzrsTrackObject::new(&path)?;
-    track_.load_tags(&conf)?;
-    if conf.directory_move_on_save && !conf.directory_output.is_empty() {
-        let tr_file_name = Path::new(&track_.file_path).file_name().unwrap_or_default();
-        let mut new_path = PathBuf::from(conf.directory_output);
-        new_path.push(tr_file_name);
-        if let Err(e) = std::fs::rename(&track_.file_path, new_path) {
-            errors.push(e.to_string())
+    let entry = match conf.history_enabled {
+        true => {
+            let batch = batch.unwrap_or_else(new_batch_id);
+            Some(history.lock().unwrap().record(&path, &batch, "save")?)
+        }
+        false => None,
+    };
+    let (saved, errors) = match files::save_track(&path, &tags, &conf) {
+        Ok(saved) => saved,
+        Err(err) => {
+            if let Some(id) = entry {
+                let _ = history.lock().unwrap().discard(&id);
+            };
+            return Err(err);
+        }
+    };
+    if let Some(id) = entry {
+        let mut history = history.lock().unwrap();
+        if saved.file_path != path {
+            history.set_path(&id, &saved.file_path)?;
         };
-    }
-    let mut t = tracks.lock().unwrap();
-    let tr = match t.get_track_obj_mut(&path) {
-        Some(tr) => tr,
-        None => return Err(format!("Cannot find inner DzrsTrackObject for {}", path)),
+        history.prune(&conf)?;
     };
-    tr.tags = track_.tags;
-    tr.tags_pictures = track_.tags_pictures;
-    tr.tags_to_save = track_.tags_to_save;
-    tr.tags_status = DzrsTrackObjectTagState::Finalized;
+    tracks.lock().unwrap().apply_saved(&path, saved)?;
     if !errors.is_empty() {
         Err(errors.join("\n"))
     } else {
@@ -434,17 +507,190 @@ async fn save_tags(
     }
 }
 
-// Deletes files from given paths
+// Lists the history entries of a file, most recent first
+#[tauri::command]
+async fn tracks_history(path: String, history: State<'_, Mutex<DzrsHistory>>) -> Result<Vec<DzrsHistoryEntry>, String> {
+    Ok(history.lock().unwrap().entries_for(&path))
+}
+
+// Reverts a single history entry, then reloads the tags of the inner DzrsTrackObject
+#[tauri::command]
+async fn tracks_history_revert(
+    id: String,
+    tracks: State<'_, Mutex<DzrsTrackObjectWrapper>>,
+    history: State<'_, Mutex<DzrsHistory>>,
+    config: State<'_, Mutex<DzrsConfiguration>>,
+) -> Result<(), String> {
+    let conf = config.lock().unwrap().parsed();
+    let path = history.lock().unwrap().revert(&id, &new_batch_id(), &conf)?;
+    reload_reverted(&[path], &tracks, &conf);
+    Ok(())
+}
+
+// Reverts every history entry of a batch, then reloads the tags of the inner DzrsTrackObjects
+// Errors with a vector of each entry that could not be reverted
 #[tauri::command]
-async fn delete_files(paths: Vec<String>, tracks: State<'_, Mutex<DzrsTrackObjectWrapper>>) -> Result<(), String> {
+async fn tracks_history_revert_batch(
+    batch: String,
+    tracks: State<'_, Mutex<DzrsTrackObjectWrapper>>,
+    history: State<'_, Mutex<DzrsHistory>>,
+    config: State<'_, Mutex<DzrsConfiguration>>,
+) -> Result<(), Vec<String>> {
+    let conf = config.lock().unwrap().parsed();
+    let paths = history.lock().unwrap().revert_batch(&batch, &new_batch_id(), &conf)?;
+    reload_reverted(&paths, &tracks, &conf);
+    Ok(())
+}
+
+fn reload_reverted(
+    paths: &[String],
+    tracks: &State<'_, Mutex<DzrsTrackObjectWrapper>>,
+    conf: &DzrsConfigurationParsed,
+) {
     let mut t = tracks.lock().unwrap();
+    for p in paths {
+        if let Some(tr) = t.get_track_obj_mut(p) {
+            if let Ok(new_tr) = DzrsTrackObject::new(p) {
+                tr.file_size = new_tr.file_size;
+                tr.file_modified = new_tr.file_modified;
+            };
+            let _ = tr.load_tags(conf);
+        };
+    }
+}
+
+// Adds, removes or renames an extra tag over the given tags, which are then stored as the tags_to_save of the inner
+// DzrsTrackObject, the frontend passes its tags_to_save so that unsaved edits are kept
+#[tauri::command]
+async fn tracks_extra_tag(
+    path: String,
+    tags: DzrsTrackObjectTags,
+    edit: DzrsExtraTagEdit,
+    tracks: State<'_, Mutex<DzrsTrackObjectWrapper>>,
+) -> Result<(), String> {
+    let mut tags = tags;
+    tags.edit_extra_tag(edit)?;
+    let mut t = tracks.lock().unwrap();
+    match t.get_track_obj_mut(&path) {
+        Some(tr) => {
+            tr.tags_to_save = tags;
+            Ok(())
+        }
+        None => Err(format!("Cannot find inner DzrsTrackObject for {}", path)),
+    }
+}
+
+// Renames files from their current tags using the file_template, the extension is kept
+// Errors with a vector of each file that could not be renamed, e.g. when the new name collides with another file
+#[tauri::command]
+async fn tracks_rename(
+    paths: Vec<String>,
+    tracks: State<'_, Mutex<DzrsTrackObjectWrapper>>,
+    config: State<'_, Mutex<DzrsConfiguration>>,
+) -> Result<(), Vec<String>> {
+    let conf = config.lock().unwrap().parsed();
+    let mut t = tracks.lock().unwrap();
+    let mut errors = Vec::new();
+
+    for p in paths {
+        if let Err(err) = t.rename_from_template(&p, &conf) {
+            errors.push(err);
+        };
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+// Reads the embedded pictures of a file, they are not part of the loaded tags and are requested when displayed
+#[tauri::command]
+async fn tracks_pictures(
+    path: String,
+    tracks: State<'_, Mutex<DzrsTrackObjectWrapper>>,
+) -> Result<Vec<DzrsTrackObjectPicture>, String> {
+    reload_pictures(&path, &tracks)
+}
+
+// Exports the embedded cover of a file as cover.jpg or folder.jpg within the same directory
+#[tauri::command]
+async fn tracks_picture_export(
+    path: String,
+    file_name: String,
+    config: State<'_, Mutex<DzrsConfiguration>>,
+) -> Result<(), String> {
+    let conf = config.lock().unwrap().parsed();
+    files::export_picture(&path, &file_name, &conf)
+}
+
+// Embeds an image from disk into a file as the given picture type, then reloads the pictures of the inner DzrsTrackObject
+// tags_to_save are kept, so unsaved edits are not lost
+#[tauri::command]
+async fn tracks_picture_import(
+    path: String,
+    image_path: String,
+    pic_type: String,
+    tracks: State<'_, Mutex<DzrsTrackObjectWrapper>>,
+    config: State<'_, Mutex<DzrsConfiguration>>,
+) -> Result<(), String> {
+    let conf = config.lock().unwrap().parsed();
+    files::import_picture(&path, &image_path, &pic_type, &conf)?;
+    reload_pictures(&path, &tracks).map(|_| ())
+}
+
+// Removes the pictures of the given type from a file, then reloads the pictures of the inner DzrsTrackObject
+#[tauri::command]
+async fn tracks_picture_remove(
+    path: String,
+    pic_type: String,
+    tracks: State<'_, Mutex<DzrsTrackObjectWrapper>>,
+    config: State<'_, Mutex<DzrsConfiguration>>,
+) -> Result<(), String> {
+    let conf = config.lock().unwrap().parsed();
+    files::remove_pictures(&path, &pic_type, &conf)?;
+    reload_pictures(&path, &tracks).map(|_| ())
+}
+
+fn reload_pictures(
+    path: &str,
+    tracks: &State<'_, Mutex<DzrsTrackObjectWrapper>>,
+) -> Result<Vec<DzrsTrackObjectPicture>, String> {
+    let pictures = open_audio_file(path)?.read_pictures();
+    let metadata = std::fs::metadata(path).map_err(|err| err.to_string())?;
+    let mut t = tracks.lock().unwrap();
+    match t.get_track_obj_mut(path) {
+        Some(tr) => {
+            // Importing or removing pictures modifies the file
+            tr.file_size = metadata.len();
+            tr.file_modified = files::modified_millis(&metadata);
+            tr.tags_pictures = pictures.clone();
+            tr.pictures_loaded = true;
+            Ok(pictures)
+        }
+        None => Err(format!("Cannot find inner DzrsTrackObject for {}", path)),
+    }
+}
+
+// Deletes files from given paths
+#[tauri::command]
+async fn delete_files(
+    paths: Vec<String>,
+    tracks: State<'_, Mutex<DzrsTrackObjectWrapper>>,
+    config: State<'_, Mutex<DzrsConfiguration>>,
+) -> Result<(), String> {
+    let conf = config.lock().unwrap().parsed();
     let mut errors: Vec<String> = Vec::new();
     for p in paths {
-        if let Err(e) = t.remove_track(&p) {
-            errors.push(e)
+        // Files go to the trash unless permanent deletion is enabled, tracks are only dropped once their file is gone
+        // and the tracks are not locked meanwhile
+        let result = match conf.directory_delete_permanently {
+            true => std::fs::remove_file(&p).map_err(|err| err.to_string()),
+            false => trash_file(&p),
         };
-        if let Err(e) = std::fs::remove_file(p) {
-            errors.push(e.to_string())
+        if let Err(e) = result.and_then(|_| tracks.lock().unwrap().remove_track(&p)) {
+            errors.push(e)
         };
     }
     if !errors.is_empty() {
@@ -464,7 +710,9 @@ async fn watch_dir(
     dir: String,
     window: Window,
     watcher: State<'_, Arc<Mutex<Option<RecommendedWatcher>>>>,
+    config: State<'_, Mutex<DzrsConfiguration>>,
 ) -> Result<(), String> {
+    let conf = config.lock().unwrap().parsed();
     let mut w: RecommendedWatcher =
         match recommended_watcher(move |res: Result<notify::Event, notify::Error>| match res {
             Ok(e) => {
@@ -476,7 +724,11 @@ async fn watch_dir(
             Err(err) => return Err(err.to_string()),
         };
 
-    if let Err(err) = w.watch(Path::new(&dir), RecursiveMode::NonRecursive) {
+    let mode = match conf.directory_recursive {
+        true => RecursiveMode::Recursive,
+        false => RecursiveMode::NonRecursive,
+    };
+    if let Err(err) = w.watch(Path::new(&dir), mode) {
         return Err(format!("Error watching {dir}, {err}"));
     };
 
@@ -496,12 +748,17 @@ async fn browse_cmd(path: String) -> Result<(), String> {
 fn main() {
     let app_dir = platform_app_dir();
     let config_path = app_dir.join("config.json");
+    let index_path = app_dir.join("library.json");
+    let history_path = app_dir.join("history");
+    let cache_path = app_dir.join("cache");
     if !app_dir.exists() {
         let _ = std::fs::create_dir_all(app_dir);
     }
     let config: Mutex<DzrsConfiguration> = Mutex::new(DzrsConfiguration::load(config_path));
+    let tagger: DeezerTagger = DeezerTagger::with_cache(cache_path, &config.lock().unwrap().parsed());
     let tracks_obj: Mutex<DzrsTrackObjectWrapper> = Mutex::new(DzrsTrackObjectWrapper::default());
-    let tagger: DeezerTagger = DeezerTagger::new();
+    let index: Mutex<DzrsLibraryIndex> = Mutex::new(DzrsLibraryIndex::load(index_path));
+    let history: Mutex<DzrsHistory> = Mutex::new(DzrsHistory::load(history_path));
     let watcher: Arc<Mutex<Option<RecommendedWatcher>>> = Arc::new(Mutex::new(None));
 
     tauri::Builder::default()
@@ -515,6 +772,8 @@ fn main() {
         })
         .manage(config)
         .manage(tracks_obj)
+        .manage(index)
+        .manage(history)
         .manage(tagger)
         .manage(watcher.clone())
         .invoke_handler(tauri::generate_handler![
@@ -527,16 +786,35 @@ fn main() {
             tracks_object,
             config_get,
             config_set,
+            cache_clear,
             tracks_fetch,
+            tracks_fetch_album,
             tracks_fetch_sources,
             tracks_fetch_sources_manual,
             tracks_source,
             tracks_reload,
+            tracks_diff,
             save_tags,
+            tracks_history,
+            tracks_history_revert,
+            tracks_history_revert_batch,
+            tracks_extra_tag,
+            tracks_rename,
+            tracks_tags_from_filename,
+            tracks_pictures,
+            tracks_picture_export,
+            tracks_picture_import,
+            tracks_picture_remove,
             delete_files,
             watch_dir,
             browse_cmd,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running dzrs");
+        .build(tauri::generate_context!())
+        .expect("error while running dzrs")
+        .run(|app, event| {
+            // Keep the fetched data of the loaded files for the next start
+            if let RunEvent::Exit = event {
+                let _ = persist_index(app);
+            };
+        });
 }
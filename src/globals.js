@@ -21,6 +21,14 @@ export const filterColumnsDirView = ref([
 ]);
 
 export const tagSeparators = [";", "; ", "/", "/ ", " / ", ",", ", ", " , "];
+export const coverSizes = [500, 800, 1000, 1200, 1400, 1800];
+export const coverQualities = [70, 80, 90, 95, 100];
+export const pictureTypes = ["CoverFront", "CoverBack", "Leaflet", "Media", "LeadArtist", "Artist", "Band", "BandLogo", "Illustration", "Other"];
+export const coverPolicies = [
+  { value: "replace", label: "Replace existing cover" },
+  { value: "add", label: "Add alongside existing pictures" },
+  { value: "keep", label: "Keep existing cover" },
+];
 export const defaultDzrsTrackObject = await invoke("tracks_object").then((res) => res);
 
 // Headerbar components share the same state using these globals